@@ -0,0 +1,814 @@
+//! A small offline stand-in for the 115.com endpoints `rs115` talks to.
+//!
+//! It speaks just enough of `app/uploadinfo`, `files/add`, `files/getid`,
+//! the `files` listing of folders, `rb/delete`, `3.0/initupload.php`, `files/index_info`, my.115.com's `nav`
+//! and the qr code login to run `rs115` end to end without network:
+//! a scripted censor word list (statuscode 414), a set of "known" SHA1s
+//! (rapid upload status 2, otherwise status 1), `errno 20004` for existing
+//! folders, statuscode 20022 for files uploaded twice to a folder, a code 911 "human verification required" switch, a minimum
+//! client app version, and one-shot scripted responses for failure injection.
+//! It also answers requests sent to it as to an http proxy, so it can stand
+//! in for the proxy and 115 at once.
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use crypto::{digest::Digest, sha1::Sha1};
+use serde_json::{json, Value};
+
+pub const DEFAULT_USER_ID: u64 = 1150000;
+pub const DEFAULT_USER_KEY: &str = "0123456789abcdef0123456789abcdef";
+pub const DEFAULT_USER_NAME: &str = "rs115";
+/// qr code login statuses.
+pub const QR_WAITING: i64 = 0;
+pub const QR_SCANNED: i64 = 1;
+pub const QR_CONFIRMED: i64 = 2;
+pub const QR_EXPIRED: i64 = -1;
+pub const QR_CANCELED: i64 = -2;
+/// Space of every mock account, used space is the sum of the uploaded files.
+pub const SPACE_TOTAL: u64 = 115 * 1024 * 1024 * 1024;
+
+/// errno returned by webapi when a folder with the same name exists.
+pub const ERRNO_DIR_EXIST: i64 = 20004;
+/// errno returned by webapi when the cookies are not (or no longer) logged in.
+pub const ERRNO_LOGIN_EXPIRED: i64 = 990001;
+/// statuscode returned by `initupload.php` when the name hits the censor list.
+pub const STATUS_FORBIDDEN_NAME: i64 = 414;
+/// statuscode / errno to script "too many requests" answers with.
+pub const STATUS_RATE_LIMITED: i64 = 429;
+/// statuscode returned by `initupload.php` when the `sig` does not match.
+pub const STATUS_BAD_SIG: i64 = 990009;
+/// statuscode / errno answered while a captcha has to be passed.
+pub const ERRNO_VERIFY_REQUIRED: i64 = 911;
+/// statuscode returned by `initupload.php` to app versions no longer accepted.
+pub const STATUS_OLD_CLIENT: i64 = 990012;
+/// statuscode returned by `initupload.php` when the folder already holds the file.
+pub const STATUS_FILE_EXISTS: i64 = 20022;
+
+const TARGET_PREFIX: &str = "U_1_";
+const END_STRING: &str = "000000";
+
+/// One request as seen by the mock, with query and form already decoded.
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    pub query: BTreeMap<String, String>,
+    pub form: BTreeMap<String, String>,
+    pub cookie: Option<String>,
+    pub user_agent: Option<String>,
+    /// host of the url when the request was sent to the mock as to a proxy
+    pub proxied_for: Option<String>,
+    pub proxy_authorization: Option<String>,
+}
+
+/// A folder created through `files/add`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Folder {
+    pub cid: u64,
+    pub pid: u64,
+    pub name: String,
+}
+
+/// A file that was rapid-uploaded (status 2).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UploadedFile {
+    pub cid: u64,
+    pub name: String,
+    pub size: String,
+    pub sha1: String,
+}
+
+#[derive(Debug)]
+struct State {
+    cookies: Option<String>,
+    user_id: u64,
+    user_key: String,
+    censor: Vec<String>,
+    known: HashSet<String>,
+    all_known: bool,
+    verification_required: bool,
+    /// uploads of older `appversion`s are rejected
+    min_app_version: Option<String>,
+    user_name: String,
+    /// vip expire unix time, 0 for non vip accounts
+    vip_expire: i64,
+    /// statuses answered to the successive polls of a qr code, the last repeats
+    qr_statuses: Vec<i64>,
+    /// qr token uid -> polls so far
+    qr_tokens: HashMap<String, usize>,
+    next_cid: u64,
+    folders: BTreeMap<u64, Folder>,
+    files: Vec<UploadedFile>,
+    scripted: HashMap<String, VecDeque<(u16, String)>>,
+    requests: Vec<RecordedRequest>,
+}
+
+/// Configures and starts a [`MockServer`].
+#[derive(Debug)]
+pub struct MockBuilder {
+    state: State,
+    port: u16,
+}
+
+impl Default for MockBuilder {
+    fn default() -> Self {
+        Self {
+            state: State {
+                cookies: None,
+                user_id: DEFAULT_USER_ID,
+                user_key: DEFAULT_USER_KEY.to_owned(),
+                censor: Vec::new(),
+                known: HashSet::new(),
+                all_known: false,
+                verification_required: false,
+                min_app_version: None,
+                user_name: DEFAULT_USER_NAME.to_owned(),
+                vip_expire: 0,
+                qr_statuses: vec![QR_WAITING, QR_SCANNED, QR_CONFIRMED],
+                qr_tokens: HashMap::new(),
+                next_cid: 1000,
+                folders: BTreeMap::new(),
+                files: Vec::new(),
+                scripted: HashMap::new(),
+                requests: Vec::new(),
+            },
+            port: 0,
+        }
+    }
+}
+
+impl MockBuilder {
+    /// Only accept requests carrying exactly this `Cookie` header.
+    pub fn cookies(mut self, cookies: &str) -> Self {
+        self.state.cookies = Some(cookies.to_owned());
+        self
+    }
+
+    pub fn user(mut self, user_id: u64, user_key: &str) -> Self {
+        self.state.user_id = user_id;
+        self.state.user_key = user_key.to_owned();
+        self
+    }
+
+    pub fn user_name(mut self, user_name: &str) -> Self {
+        self.state.user_name = user_name.to_owned();
+        self
+    }
+
+    /// Make the account a vip until `expire` (unix time).
+    pub fn vip(mut self, expire: i64) -> Self {
+        self.state.vip_expire = expire;
+        self
+    }
+
+    /// Names containing any of these words are rejected with statuscode 414.
+    pub fn censor<I, S>(mut self, words: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.state.censor.extend(words.into_iter().map(Into::into));
+        self
+    }
+
+    /// SHA1s the "cloud" already holds, those rapid-upload with status 2.
+    pub fn known_sha1<I, S>(mut self, hashes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.state
+            .known
+            .extend(hashes.into_iter().map(|h| h.as_ref().to_uppercase()));
+        self
+    }
+
+    /// Treat every SHA1 as known, what name checks with dummy hashes expect.
+    pub fn all_sha1_known(mut self) -> Self {
+        self.state.all_known = true;
+        self
+    }
+
+    /// Statuses answered to the successive polls of every login qr code, the
+    /// last one repeats. Default: waiting, scanned, confirmed.
+    pub fn qr_statuses<I: IntoIterator<Item = i64>>(mut self, statuses: I) -> Self {
+        self.state.qr_statuses = statuses.into_iter().collect();
+        self
+    }
+
+    /// Reject uploads made as a client older than `version`, with
+    /// statuscode [`STATUS_OLD_CLIENT`].
+    pub fn min_app_version(mut self, version: &str) -> Self {
+        self.state.min_app_version = Some(version.to_owned());
+        self
+    }
+
+    /// Listen on a fixed port instead of a random free one.
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    pub fn start(self) -> MockServer {
+        let listener =
+            TcpListener::bind(("127.0.0.1", self.port)).expect("mock115 should be able to bind");
+        let addr = listener.local_addr().expect("mock115 listens on tcp");
+        let state = Arc::new(Mutex::new(self.state));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let handle = {
+            let state = Arc::clone(&state);
+            let stop = Arc::clone(&stop);
+            std::thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if stop.load(Ordering::Acquire) {
+                        break;
+                    }
+                    if let Ok(stream) = stream {
+                        let state = Arc::clone(&state);
+                        std::thread::spawn(move || handle(&state, stream));
+                    }
+                }
+            })
+        };
+
+        MockServer {
+            url: format!("http://{}", addr),
+            addr,
+            stop,
+            state,
+            handle: Some(handle),
+        }
+    }
+}
+
+/// A running mock, stopped when dropped.
+pub struct MockServer {
+    url: String,
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    state: Arc<Mutex<State>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl MockServer {
+    pub fn builder() -> MockBuilder {
+        MockBuilder::default()
+    }
+
+    /// Base url serving every 115 service, e.g. `http://127.0.0.1:40123`.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Answer the next request to `path` with this status and raw body,
+    /// before any of the emulated behaviour runs. Queued per path.
+    pub fn push_response(&self, path: &str, status: u16, body: &str) {
+        self.state()
+            .scripted
+            .entry(path.to_owned())
+            .or_default()
+            .push_back((status, body.to_owned()));
+    }
+
+    pub fn add_censor(&self, word: &str) {
+        self.state().censor.push(word.to_owned());
+    }
+
+    pub fn add_known_sha1(&self, sha1: &str) {
+        self.state().known.insert(sha1.to_uppercase());
+    }
+
+    /// While set, `files/add`, `rb/delete` and `initupload.php` answer
+    /// code 911 as 115 does until someone passes the captcha in a browser.
+    pub fn set_verification_required(&self, required: bool) {
+        self.state().verification_required = required;
+    }
+
+    /// Retire the app versions older than `version`, see
+    /// [`MockBuilder::min_app_version`].
+    pub fn set_min_app_version(&self, version: &str) {
+        self.state().min_app_version = Some(version.to_owned());
+    }
+
+    pub fn set_user_key(&self, user_key: &str) {
+        self.state().user_key = user_key.to_owned();
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state().requests.clone()
+    }
+
+    pub fn requests_to(&self, path: &str) -> Vec<RecordedRequest> {
+        self.state()
+            .requests
+            .iter()
+            .filter(|r| r.path == path)
+            .cloned()
+            .collect()
+    }
+
+    /// Folders currently present, deleted ones excluded.
+    pub fn folders(&self) -> Vec<Folder> {
+        self.state().folders.values().cloned().collect()
+    }
+
+    pub fn files(&self) -> Vec<UploadedFile> {
+        self.state().files.clone()
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        // wake up the accept loop so it sees `stop`
+        let _ = TcpStream::connect(self.addr);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl std::fmt::Debug for MockServer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MockServer")
+            .field("url", &self.url)
+            .finish()
+    }
+}
+
+/// Serve one request on `stream` then close it, just enough HTTP/1.1 for reqwest.
+fn handle(state: &Mutex<State>, stream: TcpStream) {
+    let mut reader = BufReader::new(&stream);
+    let mut line = String::new();
+    if reader.read_line(&mut line).is_err() {
+        return;
+    }
+    let mut parts = line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_owned(), target.to_owned()),
+        _ => return,
+    };
+
+    let mut headers = BTreeMap::new();
+    loop {
+        let mut line = String::new();
+        match reader.read_line(&mut line) {
+            Ok(0) | Err(_) => return,
+            Ok(_) => {}
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_owned());
+        }
+    }
+
+    let len: usize = headers
+        .get("content-length")
+        .and_then(|l| l.parse().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; len];
+    if reader.read_exact(&mut body).is_err() {
+        return;
+    }
+
+    // absolute-form target: sent to the mock as to an http proxy
+    let (target, proxied_for) = match target.strip_prefix("http://") {
+        Some(rest) => {
+            let (host, target) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+            (target.to_owned(), Some(host.to_owned()))
+        }
+        None => (target, None),
+    };
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path.to_owned(), decode(query)),
+        None => (target, BTreeMap::new()),
+    };
+    let recorded = RecordedRequest {
+        method,
+        path,
+        query,
+        form: decode(&String::from_utf8_lossy(&body)),
+        cookie: headers.get("cookie").cloned(),
+        user_agent: headers.get("user-agent").cloned(),
+        proxied_for,
+        proxy_authorization: headers.get("proxy-authorization").cloned(),
+    };
+
+    let (status, body) = {
+        let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+        state.requests.push(recorded.clone());
+        let scripted = state
+            .scripted
+            .get_mut(&recorded.path)
+            .and_then(VecDeque::pop_front);
+        match scripted {
+            Some(response) => response,
+            None => route(&mut state, &recorded),
+        }
+    };
+
+    let response = format!(
+        "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    let mut stream = &stream;
+    let _ = stream.write_all(response.as_bytes());
+    let _ = stream.flush();
+}
+
+fn route(state: &mut State, req: &RecordedRequest) -> (u16, String) {
+    if state.verification_required {
+        let body = match req.path.as_str() {
+            "/3.0/initupload.php" => {
+                json!({"statuscode": ERRNO_VERIFY_REQUIRED, "statusmsg": "please verify"})
+            }
+            "/files/add" | "/rb/delete" => {
+                json!({"state": false, "errno": ERRNO_VERIFY_REQUIRED, "error": "please verify"})
+            }
+            _ => Value::Null,
+        };
+        if !body.is_null() {
+            return (200, body.to_string());
+        }
+    }
+    let body = match (req.method.as_str(), req.path.as_str()) {
+        ("GET", "/app/uploadinfo") => upload_info(state, req),
+        ("POST", "/files/add") => create_folder(state, req),
+        ("GET", "/files/getid") => folder_id(state, req),
+        ("GET", "/files") => list_folders(state, req),
+        ("POST", "/rb/delete") => delete(state, req),
+        ("POST", "/3.0/initupload.php") => init_upload(state, req),
+        ("GET", "/") if req.query.get("ac").map(String::as_str) == Some("nav") => nav(state, req),
+        ("GET", "/files/index_info") => space_info(state, req),
+        ("GET", "/api/1.0/web/1.0/token/") => qr_token(state),
+        ("GET", "/get/status/") => qr_status(state, req),
+        ("POST", "/app/1.0/web/1.0/login/qrcode/") => qr_login(state, req),
+        _ => {
+            return (
+                404,
+                json!({"state": false, "error": "not found"}).to_string(),
+            )
+        }
+    };
+    (200, body.to_string())
+}
+
+fn logged_in(state: &State, req: &RecordedRequest) -> bool {
+    match state.cookies {
+        Some(ref cookies) => req.cookie.as_ref() == Some(cookies),
+        None => true,
+    }
+}
+
+fn login_expired() -> Value {
+    json!({"state": false, "errno": ERRNO_LOGIN_EXPIRED, "error": "\\u767b\\u5f55\\u8d85\\u65f6"})
+}
+
+fn upload_info(state: &State, req: &RecordedRequest) -> Value {
+    if !logged_in(state, req) {
+        return login_expired();
+    }
+    json!({
+        "state": true,
+        "user_id": state.user_id,
+        "userkey": state.user_key,
+        "size_limit": 123480309760u64,
+        "size_limit_yun": 123480309760u64,
+        "max_dir_level": 25,
+        "max_dir_level_yun": 64,
+        "max_file_num": 50000,
+        "max_file_num_yun": 50000,
+        "upload_allowed": true,
+        "upload_allowed_msg": "",
+        "type_limit": [],
+        "file_range": {"2": "0-4294967296", "1": "4294967296-42949672960"},
+        "isp_type": 0,
+        "show_size_limit": "115GB",
+    })
+}
+
+fn nav(state: &State, req: &RecordedRequest) -> Value {
+    if !logged_in(state, req) {
+        return json!({"state": false, "error": "not logged in"});
+    }
+    json!({
+        "state": true,
+        "data": {
+            "user_id": state.user_id,
+            "user_name": state.user_name,
+            "vip": i64::from(state.vip_expire > 0),
+            "expire": state.vip_expire,
+            "face": "",
+        },
+    })
+}
+
+fn space_info(state: &State, req: &RecordedRequest) -> Value {
+    if !logged_in(state, req) {
+        return login_expired();
+    }
+    let used: u64 = state
+        .files
+        .iter()
+        .filter_map(|f| f.size.parse::<u64>().ok())
+        .sum();
+    json!({
+        "state": true,
+        "error": "",
+        "data": {
+            "space_info": {
+                "all_total": {"size": SPACE_TOTAL, "size_format": "115GB"},
+                "all_remain": {"size": SPACE_TOTAL.saturating_sub(used)},
+                "all_use": {"size": used},
+            },
+        },
+    })
+}
+
+fn qr_token(state: &mut State) -> Value {
+    let uid = format!("qr{}", state.qr_tokens.len() + 1);
+    let time = 1650000000;
+    let sign = sha1(&format!("{}{}", uid, time));
+    state.qr_tokens.insert(uid.clone(), 0);
+    json!({
+        "state": 1,
+        "code": 0,
+        "message": "",
+        "data": {
+            "uid": uid,
+            "time": time,
+            "sign": sign,
+            "qrcode": format!("https://115.com/scan/dg-{}", uid),
+        },
+    })
+}
+
+fn qr_status(state: &mut State, req: &RecordedRequest) -> Value {
+    let get = |k: &str| req.query.get(k).cloned().unwrap_or_default();
+    let uid = get("uid");
+    if sha1(&format!("{}{}", uid, get("time"))) != get("sign") {
+        return json!({"state": 0, "code": 40101017, "message": "sign invalid"});
+    }
+    let polls = match state.qr_tokens.get_mut(&uid) {
+        Some(polls) => polls,
+        None => return json!({"state": 0, "code": 40101018, "message": "unknown uid"}),
+    };
+    let status = state
+        .qr_statuses
+        .get(*polls)
+        .or(state.qr_statuses.last())
+        .copied()
+        .unwrap_or(QR_WAITING);
+    *polls += 1;
+    json!({"state": 1, "code": 0, "message": "", "data": {"msg": "", "status": status}})
+}
+
+/// Statuses answered so far to `uid`.
+fn qr_reached(state: &State, uid: &str) -> Option<i64> {
+    let polls = *state.qr_tokens.get(uid)?;
+    let seen = state
+        .qr_statuses
+        .get(..polls.min(state.qr_statuses.len()))?;
+    seen.last().copied()
+}
+
+fn qr_login(state: &mut State, req: &RecordedRequest) -> Value {
+    let uid = req.form.get("account").cloned().unwrap_or_default();
+    if qr_reached(state, &uid) != Some(QR_CONFIRMED) {
+        return json!({"state": 0, "code": 40101019, "message": "not confirmed"});
+    }
+    let cookies = state
+        .cookies
+        .get_or_insert_with(|| {
+            format!(
+                "UID={}_A1_1650000000; CID=mockcid; SEID=mockseid",
+                state.user_id
+            )
+        })
+        .clone();
+    let cookie: BTreeMap<_, _> = cookies
+        .split("; ")
+        .filter_map(|pair| pair.split_once('='))
+        .collect();
+    json!({
+        "state": 1,
+        "code": 0,
+        "message": "",
+        "data": {"user_id": state.user_id, "user_name": state.user_name, "cookie": cookie},
+    })
+}
+
+fn create_folder(state: &mut State, req: &RecordedRequest) -> Value {
+    if !logged_in(state, req) {
+        return login_expired();
+    }
+    let pid: u64 = req
+        .form
+        .get("pid")
+        .and_then(|p| p.parse().ok())
+        .unwrap_or(0);
+    let name = req.form.get("cname").cloned().unwrap_or_default();
+    if name.is_empty() {
+        return json!({"state": false, "errno": 20001, "error": "empty name"});
+    }
+    if state
+        .folders
+        .values()
+        .any(|f| f.pid == pid && f.name == name)
+    {
+        return json!({"state": false, "errno": ERRNO_DIR_EXIST, "error": "\\u8be5\\u76ee\\u5f55\\u540d\\u79f0\\u5df2\\u5b58\\u5728\\u3002"});
+    }
+    state.next_cid += 1;
+    let cid = state.next_cid;
+    state.folders.insert(
+        cid,
+        Folder {
+            cid,
+            pid,
+            name: name.clone(),
+        },
+    );
+    json!({
+        "state": true,
+        "error": "",
+        "errno": "",
+        "aid": 1,
+        "cid": cid.to_string(),
+        "cname": name,
+        "file_id": cid.to_string(),
+        "file_name": name,
+    })
+}
+
+/// The cid of the folder at the `path` query, 0 when there is none.
+fn folder_id(state: &State, req: &RecordedRequest) -> Value {
+    if !logged_in(state, req) {
+        return login_expired();
+    }
+    let path = req.query.get("path").cloned().unwrap_or_default();
+    let mut cid = Some(0);
+    for name in path.split('/').filter(|name| !name.is_empty()) {
+        cid = cid.and_then(|pid| {
+            state
+                .folders
+                .values()
+                .find(|f| f.pid == pid && f.name == name)
+                .map(|f| f.cid)
+        });
+    }
+    json!({
+        "state": true,
+        "error": "",
+        "errno": "",
+        "id": cid.unwrap_or(0).to_string(),
+        "is_private": "0",
+    })
+}
+
+/// A page of the folders in the `cid` query, by `offset` and `limit`; files
+/// are never listed, as if `nf=1` was always asked.
+fn list_folders(state: &State, req: &RecordedRequest) -> Value {
+    if !logged_in(state, req) {
+        return login_expired();
+    }
+    let number = |k: &str, default: usize| {
+        req.query
+            .get(k)
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(default)
+    };
+    let cid = number("cid", 0) as u64;
+    let folders: Vec<&Folder> = state.folders.values().filter(|f| f.pid == cid).collect();
+    let data: Vec<Value> = folders
+        .iter()
+        .skip(number("offset", 0))
+        .take(number("limit", 20))
+        .map(|f| json!({"cid": f.cid.to_string(), "pid": f.pid.to_string(), "n": f.name}))
+        .collect();
+    json!({
+        "state": true,
+        "error": "",
+        "errno": "",
+        "cid": cid.to_string(),
+        "count": folders.len(),
+        "data": data,
+    })
+}
+
+fn delete(state: &mut State, req: &RecordedRequest) -> Value {
+    if !logged_in(state, req) {
+        return login_expired();
+    }
+    let targets: Vec<u64> = req
+        .form
+        .iter()
+        .filter(|(k, _)| k.starts_with("fid["))
+        .filter_map(|(_, v)| v.parse().ok())
+        .collect();
+    if targets.is_empty() {
+        return json!({"state": false, "errno": 20130827, "error": "nothing to delete"});
+    }
+    for cid in targets {
+        state.folders.remove(&cid);
+        state.files.retain(|f| f.cid != cid);
+    }
+    json!({"state": true, "error": "", "errno": ""})
+}
+
+fn init_upload(state: &mut State, req: &RecordedRequest) -> Value {
+    if let Some(ref min) = state.min_app_version {
+        let version = req.query.get("appversion").map(String::as_str);
+        if version.is_none_or(|v| version_parts(v) < version_parts(min)) {
+            return json!({"statuscode": STATUS_OLD_CLIENT, "statusmsg": "please upgrade the client"});
+        }
+    }
+    let form = &req.form;
+    let get = |k: &str| form.get(k).cloned().unwrap_or_default();
+    let (user_id, file_id, quick_id, target) =
+        (get("userid"), get("fileid"), get("quickid"), get("target"));
+
+    let hash = sha1(&format!(
+        "{}{}{}{}{}",
+        user_id, file_id, quick_id, target, "0"
+    ));
+    let sig = sha1(&format!("{}{}{}", state.user_key, hash, END_STRING));
+    if user_id != state.user_id.to_string() || req.query.get("sig") != Some(&sig) {
+        return json!({"statuscode": STATUS_BAD_SIG, "statusmsg": "sig invalid", "status": 0});
+    }
+
+    let name = get("filename");
+    if state.censor.iter().any(|w| name.contains(w.as_str())) {
+        return json!({
+            "statuscode": STATUS_FORBIDDEN_NAME,
+            "statusmsg": "\\u6587\\u4ef6\\u540d\\u5305\\u542b\\u654f\\u611f\\u8bcd",
+        });
+    }
+
+    let file_id = file_id.to_uppercase();
+    if !(state.all_known || state.known.contains(&file_id)) {
+        return json!({
+            "request": "",
+            "status": 1,
+            "statuscode": 0,
+            "statusmsg": "",
+            "target": target,
+            "version": "V4.0",
+        });
+    }
+
+    let cid = target
+        .strip_prefix(TARGET_PREFIX)
+        .and_then(|c| c.parse().ok())
+        .unwrap_or(0);
+    if state
+        .files
+        .iter()
+        .any(|f| f.cid == cid && f.name == name && f.sha1 == file_id)
+    {
+        return json!({"statuscode": STATUS_FILE_EXISTS, "statusmsg": "file exists", "status": 0});
+    }
+    state.files.push(UploadedFile {
+        cid,
+        name,
+        size: get("filesize"),
+        sha1: file_id,
+    });
+    json!({
+        "request": "",
+        "status": 2,
+        "statuscode": 0,
+        "statusmsg": "",
+        "pickcode": "mock",
+        "target": target,
+        "version": "V4.0",
+    })
+}
+
+/// `"29.0.1"` as `[29, 0, 1]`, so versions compare numerically.
+fn version_parts(version: &str) -> Vec<u64> {
+    version.split('.').map(|n| n.parse().unwrap_or(0)).collect()
+}
+
+fn decode(s: &str) -> BTreeMap<String, String> {
+    form_urlencoded::parse(s.as_bytes()).into_owned().collect()
+}
+
+fn sha1(content: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.input_str(content);
+    hasher.result_str()
+}
@@ -0,0 +1,40 @@
+use mock115::MockServer;
+
+const USAGE: &str = "usage: mock115 [--port PORT] [--cookies COOKIES] [--censor WORD]... [--known SHA1]... [--all-known]";
+
+fn main() {
+    let mut builder = MockServer::builder();
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next().unwrap_or_else(|| {
+                eprintln!("missing value for {}\n{}", arg, USAGE);
+                std::process::exit(1);
+            })
+        };
+        builder = match arg.as_str() {
+            "--port" => match value().parse() {
+                Ok(port) => builder.port(port),
+                Err(_) => {
+                    eprintln!("port must be a number");
+                    std::process::exit(1);
+                }
+            },
+            "--cookies" => builder.cookies(&value()),
+            "--censor" => builder.censor([value()]),
+            "--known" => builder.known_sha1([value()]),
+            "--all-known" => builder.all_sha1_known(),
+            _ => {
+                eprintln!("{}", USAGE);
+                std::process::exit(1);
+            }
+        };
+    }
+
+    let server = builder.start();
+    println!("mock115 listening on {}", server.url());
+    loop {
+        std::thread::park();
+    }
+}
@@ -0,0 +1,690 @@
+//! `rs115 agent`: keeps the unlocked profiles in memory and makes the 115
+//! calls for the other `rs115` processes of the same user, in the spirit of
+//! ssh-agent.
+//!
+//! Clients connect to a unix socket, send one json [`Request`] line and read
+//! one json answer. Cookies and user keys never leave the agent: clients get
+//! the results of the calls only. Connections from other users are refused
+//! by their peer credentials.
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::api::{AccountInfo, RapidUploadRequest, SessionConfig};
+use crate::error::{Error, Result};
+use crate::profiles::ProfileCache;
+use crate::store::SessionStore;
+use crate::upload::Session;
+
+/// Socket of the running agent, for clients and the agent itself.
+pub const AGENT_SOCK_ENV: &str = "RS115_AGENT_SOCK";
+/// How long a client may take to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest request line the agent reads, longer ones are refused.
+const MAX_REQUEST: u64 = 64 * 1024;
+/// Longest answer line a client reads.
+const MAX_RESPONSE: u64 = 4 * 1024 * 1024;
+
+/// What a client asks the agent; `profile` is the cache default when `None`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Request {
+    /// the profile names, the default one and the user ids, without secrets
+    Profiles,
+    AccountInfo {
+        profile: Option<String>,
+    },
+    CreateFolder {
+        profile: Option<String>,
+        pid: u64,
+        name: String,
+    },
+    Delete {
+        profile: Option<String>,
+        pid: u64,
+        targets: Vec<u64>,
+    },
+    FolderId {
+        profile: Option<String>,
+        path: String,
+    },
+    FindFolder {
+        profile: Option<String>,
+        pid: u64,
+        name: String,
+    },
+    RapidUpload {
+        profile: Option<String>,
+        request: RapidUploadRequest,
+    },
+    /// forget the sessions and exit
+    Stop,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Response {
+    Ok(Value),
+    Err(RemoteError),
+}
+
+/// The line answering a [`Request`].
+#[derive(Debug, Serialize, Deserialize)]
+struct Answer {
+    #[serde(flatten)]
+    response: Response,
+    /// requests to 115 the agent made again while answering; calls of other
+    /// clients on the same profile at the same time may be counted too
+    #[serde(default, skip_serializing_if = "is_zero")]
+    retries: u64,
+}
+
+fn is_zero(n: &u64) -> bool {
+    *n == 0
+}
+
+/// An [`Error`] on the wire, keeping the variants callers match on.
+#[derive(Debug, Serialize, Deserialize)]
+struct RemoteError {
+    kind: String,
+    #[serde(default)]
+    code: i64,
+    message: String,
+    /// the second field of the variants that have one
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    detail: String,
+}
+
+impl From<Error> for RemoteError {
+    fn from(e: Error) -> Self {
+        let message = e.to_string();
+        let none = String::new();
+        let (kind, code, message, detail) = match e {
+            Error::Network(e) => ("network", 0, e.to_string(), none),
+            Error::RemoteNetwork(message) => ("network", 0, message, none),
+            Error::Decode { reason, snippet } => ("decode", 0, snippet, reason),
+            Error::ForbiddenName => ("forbidden_name", 0, message, none),
+            Error::DirExist => ("dir_exist", 0, message, none),
+            Error::FileExists => ("file_exists", 0, message, none),
+            Error::FolderNotFound(path) => ("folder_not_found", 0, path, none),
+            Error::AuthExpired => ("auth_expired", 0, message, none),
+            Error::MissingUserKey => ("missing_user_key", 0, message, none),
+            Error::StaleUserKey { code, message } => ("stale_user_key", code, message, none),
+            Error::ClientRetired {
+                app_version,
+                message,
+            } => ("client_retired", 0, message, app_version),
+            Error::NotLoggedIn => ("not_logged_in", 0, message, none),
+            Error::VerificationRequired { message } => ("verification_required", 0, message, none),
+            Error::RapidUpload { status, message } => ("rapid_upload", status, message, none),
+            Error::Api { code, message } => ("api", code, message, none),
+            Error::HttpStatus { status, snippet } => ("http_status", status.into(), snippet, none),
+            Error::UnknownProfile(name) => ("unknown_profile", 0, name, none),
+            _ => ("other", 0, message, none),
+        };
+        RemoteError {
+            kind: kind.to_owned(),
+            code,
+            message,
+            detail,
+        }
+    }
+}
+
+impl From<RemoteError> for Error {
+    fn from(e: RemoteError) -> Self {
+        let RemoteError {
+            kind,
+            code,
+            message,
+            detail,
+        } = e;
+        match kind.as_str() {
+            "network" => Error::RemoteNetwork(message),
+            "decode" => Error::Decode {
+                reason: detail,
+                snippet: message,
+            },
+            "forbidden_name" => Error::ForbiddenName,
+            "dir_exist" => Error::DirExist,
+            "file_exists" => Error::FileExists,
+            "folder_not_found" => Error::FolderNotFound(message),
+            "auth_expired" => Error::AuthExpired,
+            "missing_user_key" => Error::MissingUserKey,
+            "stale_user_key" => Error::StaleUserKey { code, message },
+            "client_retired" => Error::ClientRetired {
+                app_version: detail,
+                message,
+            },
+            "not_logged_in" => Error::NotLoggedIn,
+            "verification_required" => Error::VerificationRequired { message },
+            "rapid_upload" => Error::RapidUpload {
+                status: code,
+                message,
+            },
+            "api" => Error::Api { code, message },
+            "http_status" => Error::HttpStatus {
+                status: code.try_into().unwrap_or_default(),
+                snippet: message,
+            },
+            "unknown_profile" => Error::UnknownProfile(message),
+            _ => Error::Agent(message),
+        }
+    }
+}
+
+/// `$RS115_AGENT_SOCK`, else `agent.sock` in `$XDG_RUNTIME_DIR/rs115`, else
+/// in a per user directory of the temp dir.
+pub fn default_socket_path() -> PathBuf {
+    if let Some(path) = std::env::var_os(AGENT_SOCK_ENV) {
+        return PathBuf::from(path);
+    }
+    let dir = match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir).join("rs115"),
+        // SAFETY: geteuid can not fail
+        _ => std::env::temp_dir().join(format!("rs115-{}", unsafe { libc::geteuid() })),
+    };
+    dir.join("agent.sock")
+}
+
+/// Listen on `path`, in a directory only the owner can enter. A socket left
+/// by an agent that is gone is replaced, a live one is an error.
+pub fn bind(path: &Path) -> Result<UnixListener> {
+    if let Some(dir) = path.parent() {
+        let mut builder = std::fs::DirBuilder::new();
+        builder.recursive(true);
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        builder.create(dir)?;
+    }
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AddrInUse,
+                format!("an agent already listens on {}", path.display()),
+            )
+            .into());
+        }
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+/// The agent: the profiles of its store and their sessions, built on first
+/// use. All clients share the sessions and so their rate limits.
+#[derive(Debug)]
+pub struct Agent {
+    store: Mutex<Box<dyn SessionStore>>,
+    cache: Mutex<ProfileCache>,
+    config: SessionConfig,
+    // locked before `cache`, itself locked before `store`
+    sessions: Mutex<BTreeMap<String, Arc<Session>>>,
+}
+
+impl Agent {
+    /// Load the profiles of `store`, which must have some.
+    pub fn new(store: Box<dyn SessionStore>, config: SessionConfig) -> Result<Self> {
+        let cache = store.load()?;
+        if cache.profiles.is_empty() {
+            return Err(Error::NotLoggedIn);
+        }
+        Ok(Self {
+            store: Mutex::new(store),
+            cache: Mutex::new(cache),
+            config,
+            sessions: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    /// Answer clients, each connection on its own thread, until one asks to
+    /// stop; then wait for the calls under way and remove the socket.
+    pub fn serve(self, listener: UnixListener) -> Result<()> {
+        let addr = listener.local_addr()?;
+        let path = addr.as_pathname();
+        let stopping = AtomicBool::new(false);
+        std::thread::scope(|scope| {
+            for stream in listener.incoming() {
+                if stopping.load(Ordering::SeqCst) {
+                    break;
+                }
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        eprintln!("agent: accept failed, {}", e);
+                        continue;
+                    }
+                };
+                let (agent, stopping) = (&self, &stopping);
+                scope.spawn(move || match agent.handle(stream) {
+                    Ok(true) => {
+                        stopping.store(true, Ordering::SeqCst);
+                        // wake the accept loop up so it sees the stop
+                        if let Some(path) = path {
+                            let _ = UnixStream::connect(path);
+                        }
+                    }
+                    Ok(false) => {}
+                    Err(e) => eprintln!("agent: {}", e),
+                });
+            }
+        });
+        if let Some(path) = path {
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// Answer one connection, `true` if it asked to stop.
+    fn handle(&self, stream: UnixStream) -> Result<bool> {
+        let uid = peer_uid(&stream)?;
+        // SAFETY: geteuid can not fail
+        if uid != unsafe { libc::geteuid() } {
+            return Err(Error::Agent(format!(
+                "refused a connection from user {}",
+                uid
+            )));
+        }
+        stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+        let line = read_line(&stream, MAX_REQUEST)?;
+
+        let mut retries = 0;
+        let (stop, response) = match line.as_deref().map(serde_json::from_str::<Request>) {
+            None => (
+                false,
+                Response::Err(
+                    Error::Agent(format!("request over {} bytes refused", MAX_REQUEST)).into(),
+                ),
+            ),
+            Some(Ok(Request::Stop)) => (true, Response::Ok(Value::Null)),
+            Some(Ok(request)) => {
+                let res = self.call(request, &mut retries);
+                self.save_refreshed_keys();
+                match res {
+                    Ok(value) => (false, Response::Ok(value)),
+                    Err(e) => (false, Response::Err(e.into())),
+                }
+            }
+            Some(Err(e)) => (
+                false,
+                Response::Err(Error::Agent(format!("bad request: {}", e)).into()),
+            ),
+        };
+        let answer = Answer { response, retries };
+        let mut stream = stream;
+        writeln!(stream, "{}", serde_json::to_string(&answer)?)?;
+        Ok(stop)
+    }
+
+    /// Make the call `request` asks for, adding the requests retried meanwhile
+    /// to `retries`.
+    fn call(&self, request: Request, retries: &mut u64) -> Result<Value> {
+        let value = match request {
+            Request::Profiles => {
+                let mut cache = lock(&self.cache).clone();
+                for profile in cache.profiles.values_mut() {
+                    profile.cookies.clear();
+                    profile.user_key = None;
+                }
+                serde_json::to_value(cache)?
+            }
+            Request::AccountInfo { profile } => {
+                self.with_session(profile, retries, |session| session.account_info())?
+            }
+            Request::CreateFolder { profile, pid, name } => {
+                self.with_session(profile, retries, |session| session.create_folder(pid, name))?
+            }
+            Request::Delete {
+                profile,
+                pid,
+                targets,
+            } => self.with_session(profile, retries, |session| {
+                session.delete_bulk(pid, targets)
+            })?,
+            Request::FolderId { profile, path } => {
+                self.with_session(profile, retries, |session| session.folder_id(&path))?
+            }
+            Request::FindFolder { profile, pid, name } => {
+                self.with_session(profile, retries, |session| session.find_folder(pid, &name))?
+            }
+            Request::RapidUpload { profile, request } => {
+                self.with_session(profile, retries, |session| session.rapid_upload(&request))?
+            }
+            Request::Stop => Value::Null,
+        };
+        Ok(value)
+    }
+
+    /// `call` on the session of `profile`, the requests it retried added to
+    /// `retries`.
+    fn with_session<T: Serialize>(
+        &self,
+        profile: Option<String>,
+        retries: &mut u64,
+        call: impl FnOnce(&Session) -> Result<T>,
+    ) -> Result<Value> {
+        let session = self.session(profile)?;
+        let before = session.retry_count();
+        let res = call(&session);
+        *retries += session.retry_count() - before;
+        Ok(serde_json::to_value(res?)?)
+    }
+
+    /// Keep the user keys sessions fetched again after 115 rejected them.
+    fn save_refreshed_keys(&self) {
+        let sessions = lock(&self.sessions);
+        let mut cache = lock(&self.cache);
+        let mut changed = false;
+        for (name, session) in sessions.iter() {
+            let profile = session.profile();
+            if session.key_refreshes() > 0 && cache.get(name) != Some(&profile) {
+                changed |= cache.insert(name, profile).is_ok();
+            }
+        }
+        if changed {
+            if let Err(e) = lock(&self.store).save(&cache) {
+                eprintln!("agent: the refreshed user key was not saved, {}", e);
+            }
+        }
+    }
+
+    /// The session of `profile`, built and given its user key by the first
+    /// call; other profiles wait meanwhile.
+    fn session(&self, profile: Option<String>) -> Result<Arc<Session>> {
+        let mut sessions = lock(&self.sessions);
+        let (name, profile) = {
+            let cache = lock(&self.cache);
+            let name = cache.resolve(profile.as_deref()).to_owned();
+            let profile = cache.get(&name).cloned();
+            (name, profile)
+        };
+        if !sessions.contains_key(&name) {
+            let profile = profile.ok_or(Error::NotLoggedIn)?;
+            let mut session = profile.builder_with(self.config.clone()).build()?;
+            session.get_key_if_none()?;
+            sessions.insert(name.clone(), Arc::new(session));
+        }
+        Ok(Arc::clone(&sessions[&name]))
+    }
+}
+
+/// `mutex` locked, even if a thread panicked while holding it.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// The next line of `stream`, `None` if it is longer than `max` bytes.
+fn read_line(stream: &UnixStream, max: u64) -> std::io::Result<Option<String>> {
+    let mut line = String::new();
+    BufReader::new(stream.take(max + 1)).read_line(&mut line)?;
+    Ok((line.len() as u64 <= max).then_some(line))
+}
+
+/// User id of the process at the other end of `stream`.
+fn peer_uid(stream: &UnixStream) -> std::io::Result<u32> {
+    let fd = stream.as_raw_fd();
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        let mut cred = libc::ucred {
+            pid: 0,
+            uid: 0,
+            gid: 0,
+        };
+        let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+        // SAFETY: `cred` and `len` are valid for writes of a ucred
+        let rc = unsafe {
+            libc::getsockopt(
+                fd,
+                libc::SOL_SOCKET,
+                libc::SO_PEERCRED,
+                &mut cred as *mut libc::ucred as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        if rc != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(cred.uid)
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    {
+        let (mut uid, mut gid) = (0, 0);
+        // SAFETY: `uid` and `gid` are valid for writes
+        if unsafe { libc::getpeereid(fd, &mut uid, &mut gid) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(uid)
+    }
+}
+
+/// Talks to a running agent; every call is one connection.
+#[derive(Debug, Clone)]
+pub struct AgentClient {
+    path: PathBuf,
+    /// shared by the clones
+    retries: Arc<AtomicU64>,
+}
+
+impl AgentClient {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            retries: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// The agent at `$RS115_AGENT_SOCK`, if set.
+    pub fn from_env() -> Option<Self> {
+        std::env::var_os(AGENT_SOCK_ENV).map(Self::new)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Requests the agent retried for the calls of this client and its
+    /// clones, like [`Session::retry_count`].
+    pub fn retry_count(&self) -> u64 {
+        self.retries.load(Ordering::Relaxed)
+    }
+
+    /// Send `request`, return the agent's answer.
+    pub fn call(&self, request: &Request) -> Result<Value> {
+        let mut stream = UnixStream::connect(&self.path).map_err(|e| {
+            Error::Agent(format!(
+                "can not reach the agent at {}: {}",
+                self.path.display(),
+                e
+            ))
+        })?;
+        writeln!(stream, "{}", serde_json::to_string(request)?)?;
+        let line = read_line(&stream, MAX_RESPONSE)?
+            .ok_or_else(|| Error::Agent(format!("answer over {} bytes refused", MAX_RESPONSE)))?;
+        let answer: Answer = serde_json::from_str(&line).map_err(|e| Error::decode(e, &line))?;
+        self.retries.fetch_add(answer.retries, Ordering::Relaxed);
+        match answer.response {
+            Response::Ok(value) => Ok(value),
+            Response::Err(e) => Err(e.into()),
+        }
+    }
+
+    fn call_as<T: serde::de::DeserializeOwned>(&self, request: &Request) -> Result<T> {
+        let value = self.call(request)?;
+        serde_json::from_value(value).map_err(|e| Error::decode(e, ""))
+    }
+
+    /// The agent's profiles, cookies and user keys left out.
+    pub fn profiles(&self) -> Result<ProfileCache> {
+        self.call_as(&Request::Profiles)
+    }
+
+    pub fn account_info(&self, profile: Option<&str>) -> Result<AccountInfo> {
+        self.call_as(&Request::AccountInfo {
+            profile: profile.map(str::to_owned),
+        })
+    }
+
+    pub fn create_folder(&self, profile: Option<&str>, pid: u64, name: String) -> Result<u64> {
+        self.call_as(&Request::CreateFolder {
+            profile: profile.map(str::to_owned),
+            pid,
+            name,
+        })
+    }
+
+    pub fn delete_bulk(&self, profile: Option<&str>, pid: u64, targets: Vec<u64>) -> Result<()> {
+        self.call(&Request::Delete {
+            profile: profile.map(str::to_owned),
+            pid,
+            targets,
+        })?;
+        Ok(())
+    }
+
+    pub fn folder_id(&self, profile: Option<&str>, path: &str) -> Result<u64> {
+        self.call_as(&Request::FolderId {
+            profile: profile.map(str::to_owned),
+            path: path.to_owned(),
+        })
+    }
+
+    pub fn find_folder(&self, profile: Option<&str>, pid: u64, name: &str) -> Result<Option<u64>> {
+        self.call_as(&Request::FindFolder {
+            profile: profile.map(str::to_owned),
+            pid,
+            name: name.to_owned(),
+        })
+    }
+
+    pub fn rapid_upload(&self, profile: Option<&str>, request: &RapidUploadRequest) -> Result<()> {
+        self.call(&Request::RapidUpload {
+            profile: profile.map(str::to_owned),
+            request: request.clone(),
+        })?;
+        Ok(())
+    }
+
+    /// Ask the agent to exit.
+    pub fn stop(&self) -> Result<()> {
+        self.call(&Request::Stop)?;
+        Ok(())
+    }
+
+    fn read_only(&self) -> Error {
+        Error::Agent(format!(
+            "the cookies are held by the agent at {}, unset ${} to change them",
+            self.path.display(),
+            AGENT_SOCK_ENV
+        ))
+    }
+}
+
+/// The agent's profiles, read only: they are changed where the agent loaded
+/// them from, then the agent restarted.
+impl SessionStore for AgentClient {
+    fn load(&self) -> Result<ProfileCache> {
+        self.profiles()
+    }
+
+    fn save(&self, _: &ProfileCache) -> Result<()> {
+        Err(self.read_only())
+    }
+
+    fn clear(&self) -> Result<()> {
+        Err(self.read_only())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peer_is_this_user_test() {
+        let (a, _b) = UnixStream::pair().unwrap();
+        // SAFETY: geteuid can not fail
+        assert_eq!(peer_uid(&a).unwrap(), unsafe { libc::geteuid() });
+    }
+
+    #[test]
+    fn long_lines_are_refused_test() {
+        let (a, mut b) = UnixStream::pair().unwrap();
+        let writer = std::thread::spawn(move || {
+            b.write_all(b"{}\n").unwrap();
+            // the reader stops early, the rest may not be taken
+            let _ = b.write_all(&[b'x'; 100_000]);
+        });
+        assert_eq!(read_line(&a, 16).unwrap().as_deref(), Some("{}\n"));
+        assert_eq!(read_line(&a, 1024).unwrap(), None);
+        drop(a);
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn answers_carry_retries_test() {
+        let answer = Answer {
+            response: Response::Ok(Value::from(7)),
+            retries: 2,
+        };
+        let wire = serde_json::to_string(&answer).unwrap();
+        assert_eq!(wire, r#"{"ok":7,"retries":2}"#);
+        let answer: Answer =
+            serde_json::from_str(r#"{"err":{"kind":"dir_exist","message":""}}"#).unwrap();
+        assert!(matches!(answer.response, Response::Err(_)));
+        assert_eq!(answer.retries, 0);
+    }
+
+    #[test]
+    fn errors_survive_the_socket_test() {
+        let round_trip = |e: Error| -> Error {
+            let wire = serde_json::to_string(&RemoteError::from(e)).unwrap();
+            serde_json::from_str::<RemoteError>(&wire).unwrap().into()
+        };
+        assert!(matches!(
+            round_trip(Error::ForbiddenName),
+            Error::ForbiddenName
+        ));
+        assert!(matches!(
+            round_trip(Error::RapidUpload {
+                status: 1,
+                message: "not in 115".into()
+            }),
+            Error::RapidUpload { status: 1, .. }
+        ));
+        assert!(matches!(
+            round_trip(Error::ClientRetired {
+                app_version: "29.0.0".into(),
+                message: "too old".into(),
+            }),
+            Error::ClientRetired { app_version, message }
+                if app_version == "29.0.0" && message == "too old"
+        ));
+        assert!(matches!(
+            round_trip(Error::StaleUserKey {
+                code: 990009,
+                message: "sig".into()
+            }),
+            Error::StaleUserKey { code: 990009, .. }
+        ));
+        assert!(matches!(
+            round_trip(Error::decode("bad json", "<html>")),
+            Error::Decode { reason, snippet } if reason == "bad json" && snippet == "<html>"
+        ));
+        assert!(matches!(
+            round_trip(Error::RemoteNetwork("connection refused".into())),
+            Error::RemoteNetwork(message) if message == "connection refused"
+        ));
+        match round_trip(Error::Decrypt) {
+            Error::Agent(message) => assert_eq!(message, Error::Decrypt.to_string()),
+            e => panic!("unexpected {:?}", e),
+        }
+    }
+}
@@ -0,0 +1,839 @@
+//! Requests and responses of the 115 api, independent of the http client.
+//!
+//! Signing, form building and response checks live here so the blocking
+//! and async sessions share them; what only the sessions use is built with
+//! the `blocking` or `async` feature.
+use crypto::{digest::Digest, sha1::Sha1};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::client::ClientIdentity;
+use crate::endpoints::Endpoints;
+#[cfg(any(feature = "blocking", feature = "async"))]
+use crate::error::{
+    Error, Result, ERRNO_DIR_EXIST, ERRNO_LOGIN_EXPIRED, ERRNO_VERIFY_REQUIRED, STATUS_BAD_SIG,
+    STATUS_FILE_EXISTS, STATUS_FORBIDDEN_NAME, STATUS_OLD_CLIENT,
+};
+use crate::network::NetworkConfig;
+use crate::ratelimit::{RateLimitConfig, RateLimiter};
+use crate::redact;
+use crate::retry::RetryPolicy;
+#[cfg(any(feature = "blocking", feature = "async"))]
+use serde::de::DeserializeOwned;
+#[cfg(any(feature = "blocking", feature = "async"))]
+use std::sync::RwLock;
+
+#[cfg(any(feature = "blocking", feature = "async"))]
+const TARGET_PREFIX: &str = r"U_1_";
+const END_STRING: &str = r"000000";
+
+#[cfg(any(feature = "blocking", feature = "async"))]
+type MayBeError = Result<()>;
+
+/// Everything about a `Session` except the account: where and how to talk to 115.
+#[derive(Debug, Clone, Default)]
+pub struct SessionConfig {
+    pub endpoints: Endpoints,
+    /// client to present as, defaults to the profile's, else [`DEFAULT_CLIENT`]
+    ///
+    /// [`DEFAULT_CLIENT`]: crate::client::DEFAULT_CLIENT
+    pub client: Option<ClientIdentity>,
+    /// overrides the user agent of `client`
+    pub user_agent: Option<String>,
+    /// total timeout of one request
+    pub timeout: Option<Duration>,
+    pub connect_timeout: Option<Duration>,
+    /// proxy, root certificates and local address
+    pub network: NetworkConfig,
+    pub retry: RetryPolicy,
+    pub rate_limit: RateLimitConfig,
+}
+
+impl SessionConfig {
+    #[cfg(any(feature = "blocking", feature = "async"))]
+    pub(crate) fn user_agent(&self) -> String {
+        match (&self.user_agent, &self.client) {
+            (Some(user_agent), _) => user_agent.clone(),
+            (None, Some(client)) => client.user_agent.clone(),
+            (None, None) => ClientIdentity::default().user_agent,
+        }
+    }
+}
+
+/// Builds a `Session` (or `AsyncSession`) from login cookies.
+#[derive(Debug, Clone)]
+pub struct SessionBuilder {
+    /// read when the session is built, which needs an http client feature
+    #[cfg_attr(not(any(feature = "blocking", feature = "async")), allow(dead_code))]
+    pub(crate) cookies: String,
+    pub(crate) config: SessionConfig,
+    pub(crate) keys: Option<(String, String)>,
+    pub(crate) limiter: Option<Arc<RateLimiter>>,
+}
+
+impl SessionBuilder {
+    pub fn new(cookies: impl Into<String>) -> Self {
+        Self {
+            cookies: cookies.into(),
+            config: SessionConfig::default(),
+            keys: None,
+            limiter: None,
+        }
+    }
+
+    pub fn config(mut self, config: SessionConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn endpoints(mut self, endpoints: Endpoints) -> Self {
+        self.config.endpoints = endpoints;
+        self
+    }
+
+    pub fn client(mut self, client: ClientIdentity) -> Self {
+        self.config.client = Some(client);
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.config.user_agent = Some(user_agent.into());
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.config.timeout = Some(timeout);
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.config.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Proxy, extra root certificates and local address to connect from.
+    pub fn network(mut self, network: NetworkConfig) -> Self {
+        self.config.network = network;
+        self
+    }
+
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.config.retry = retry;
+        self
+    }
+
+    pub fn rate_limit(mut self, rate_limit: RateLimitConfig) -> Self {
+        self.config.rate_limit = rate_limit;
+        self
+    }
+
+    /// Share an existing limiter (e.g. `Session::rate_limiter`) so several
+    /// sessions spend one budget; `rate_limit` is then ignored.
+    pub fn rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    #[cfg(any(feature = "blocking", feature = "async"))]
+    pub(crate) fn take_limiter(&mut self) -> Arc<RateLimiter> {
+        match self.limiter.take() {
+            Some(limiter) => limiter,
+            None => Arc::new(RateLimiter::new(&self.config.rate_limit)),
+        }
+    }
+
+    /// Reuse a `user_id`/`user_key` pair fetched earlier instead of asking 115 again.
+    pub fn user_key(mut self, user_id: impl Into<String>, user_key: impl Into<String>) -> Self {
+        self.keys = Some((user_id.into(), user_key.into()));
+        self
+    }
+}
+
+/// The `user_id`/`user_key` uploads are signed with. Behind a lock so a
+/// session used through `&self` can replace a key 115 stopped accepting.
+#[cfg(any(feature = "blocking", feature = "async"))]
+#[derive(Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub(crate) struct UserKeys(RwLock<KeyPair>);
+
+#[cfg(any(feature = "blocking", feature = "async"))]
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct KeyPair {
+    user_id: Option<String>,
+    user_key: Option<String>,
+}
+
+/// Whether the keys are known, not what they are.
+#[cfg(any(feature = "blocking", feature = "async"))]
+impl std::fmt::Debug for UserKeys {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let keys = self.read();
+        f.debug_struct("UserKeys")
+            .field("user_id", &redact::option(&keys.user_id))
+            .field("user_key", &redact::option(&keys.user_key))
+            .finish()
+    }
+}
+
+#[cfg(any(feature = "blocking", feature = "async"))]
+impl UserKeys {
+    pub(crate) fn new(keys: Option<(String, String)>) -> Self {
+        let (user_id, user_key) = keys.unzip();
+        Self(RwLock::new(KeyPair { user_id, user_key }))
+    }
+
+    fn read(&self) -> KeyPair {
+        self.0.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Both, or `None` until fetched.
+    pub(crate) fn get(&self) -> Option<(String, String)> {
+        let keys = self.read();
+        keys.user_id.zip(keys.user_key)
+    }
+
+    pub(crate) fn user_id(&self) -> Option<String> {
+        self.read().user_id
+    }
+
+    pub(crate) fn user_key(&self) -> Option<String> {
+        self.read().user_key
+    }
+
+    pub(crate) fn set(&self, user_id: String, user_key: String) {
+        *self.0.write().unwrap_or_else(|e| e.into_inner()) = KeyPair {
+            user_id: Some(user_id),
+            user_key: Some(user_key),
+        };
+    }
+}
+
+/// A file to rapid-upload by hash into folder `cid`, what a `115://` link describes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RapidUploadRequest {
+    pub filename: String,
+    pub file_size: u64,
+    pub sha1: String,
+    pub block_sha1: String,
+    pub cid: u64,
+}
+
+/// The `sig` of an `initupload.php` request.
+pub fn upload_sig(user_id: &str, user_key: &str, file_id: &str, target: &str) -> String {
+    let quick_id = file_id;
+    let hash = sha1(format!("{}{}{}{}{}", user_id, file_id, quick_id, target, "0").as_str());
+    let sig_string = user_key.to_owned() + &hash + END_STRING;
+    sha1(&sig_string)
+}
+
+/// Url and form of a signed `initupload.php` request.
+#[cfg(any(feature = "blocking", feature = "async"))]
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn upload_request(
+    endpoints: &Endpoints,
+    client: &ClientIdentity,
+    user_id: &str,
+    user_key: &str,
+    filename: String,
+    file_size: String,
+    total_hash: String,
+    block_hash: String,
+    cid: u64,
+) -> (String, [(&'static str, String); 10]) {
+    let pre_id = block_hash;
+    let file_id = total_hash.to_uppercase();
+    let target = TARGET_PREFIX.to_owned() + &cid.to_string();
+    let sig = upload_sig(user_id, user_key, &file_id, &target);
+
+    let param = [
+        ("preid", pre_id),
+        ("filename", filename),
+        ("quickid", file_id.clone()),
+        ("user_id", user_id.into()),
+        ("app_ver", client.app_version.clone()),
+        ("filesize", file_size),
+        ("userid", user_id.into()),
+        ("exif", "".into()),
+        ("target", target),
+        ("fileid", file_id),
+    ];
+
+    let url = format!(
+        "{}?isp={}&appid={}&appversion={}&format=json&sig={}",
+        endpoints.init_upload_url(),
+        client.isp,
+        client.appid,
+        client.app_version,
+        sig
+    );
+    (url, param)
+}
+
+#[cfg(any(feature = "blocking", feature = "async"))]
+pub(crate) fn create_dir_form(pid: u64, name: String) -> [(&'static str, String); 2] {
+    [("pid", pid.to_string()), ("cname", name)]
+}
+
+#[cfg(any(feature = "blocking", feature = "async"))]
+pub(crate) fn folder_id_query(path: &str) -> [(&'static str, &str); 1] {
+    [("path", path)]
+}
+
+/// Folders asked for per `files` listing page.
+#[cfg(any(feature = "blocking", feature = "async"))]
+pub(crate) const LIST_LIMIT: usize = 1000;
+
+/// The page of the folders in `cid` starting at `offset`, files left out.
+#[cfg(any(feature = "blocking", feature = "async"))]
+pub(crate) fn list_folders_query(cid: u64, offset: usize) -> [(&'static str, String); 7] {
+    [
+        ("aid", "1".to_owned()),
+        ("cid", cid.to_string()),
+        ("show_dir", "1".to_owned()),
+        ("nf", "1".to_owned()),
+        ("offset", offset.to_string()),
+        ("limit", LIST_LIMIT.to_string()),
+        ("format", "json".to_owned()),
+    ]
+}
+
+#[cfg(any(feature = "blocking", feature = "async"))]
+pub(crate) fn delete_form(pid: u64, target_list: Vec<u64>) -> Vec<(String, u64)> {
+    let mut param = [("pid".to_owned(), pid), ("ignore_warn".to_owned(), 1)].to_vec();
+
+    for (i, item) in target_list.into_iter().enumerate() {
+        let key = format!("fid[{}]", i);
+        let value = item;
+        param.push((key, value));
+    }
+    param
+}
+
+/// Trace a request about to be sent, with the secrets masked.
+#[cfg(any(feature = "blocking", feature = "async"))]
+pub(crate) fn trace_request(method: &str, url: &str, form: Option<&[u8]>) {
+    if tracing::enabled!(tracing::Level::TRACE) {
+        let form = form.map(|form| redact::form_str(&String::from_utf8_lossy(form)));
+        tracing::trace!(
+            method,
+            url = %redact::url(url),
+            form = form.as_deref().unwrap_or_default(),
+            "request"
+        );
+    }
+}
+
+/// One debug event per attempt of a call: latency and outcome.
+#[cfg(any(feature = "blocking", feature = "async"))]
+pub(crate) fn trace_attempt<T>(attempt: u32, started: std::time::Instant, res: &Result<T>) {
+    let latency_ms = started.elapsed().as_millis() as u64;
+    match res {
+        Ok(_) => tracing::debug!(attempt, latency_ms, "ok"),
+        Err(e) => tracing::debug!(attempt, latency_ms, code = e.code(), error = %e, "failed"),
+    }
+}
+
+/// Decode a 115 json body, non 2xx statuses and malformed json become errors.
+#[cfg(any(feature = "blocking", feature = "async"))]
+pub(crate) fn parse_body<T: DeserializeOwned>(status: u16, body: &str) -> Result<T> {
+    tracing::trace!(status, body = %crate::error::snippet(body), "response");
+    if !(200..300).contains(&status) {
+        return Err(Error::http_status(status, body));
+    }
+    serde_json::from_str(body).map_err(|e| Error::decode(e, body))
+}
+
+#[cfg(any(feature = "blocking", feature = "async"))]
+pub(crate) fn check_user_info(res: UserInfo) -> Result<(String, String)> {
+    match (res.user_id, res.userkey) {
+        (Some(user_id), Some(user_key)) if res.state != Some(false) => {
+            Ok((user_id.to_string(), user_key))
+        }
+        _ => Err(Error::AuthExpired),
+    }
+}
+
+/// `app_version` is the client the upload was made as.
+#[cfg(any(feature = "blocking", feature = "async"))]
+pub(crate) fn check_upload(res: UploadResponseJson, app_version: &str) -> MayBeError {
+    let message = res.statusmsg.js_utf8_decode();
+    match res.statuscode {
+        0 => match res.status {
+            Some(2) => Ok(()),
+            Some(status) => Err(Error::RapidUpload { status, message }),
+            None => Err(Error::decode("missing status", &format!("{:?}", res.other))),
+        },
+        STATUS_FORBIDDEN_NAME => Err(Error::ForbiddenName),
+        STATUS_FILE_EXISTS => Err(Error::FileExists),
+        ERRNO_VERIFY_REQUIRED => Err(Error::VerificationRequired { message }),
+        STATUS_OLD_CLIENT => Err(Error::ClientRetired {
+            app_version: app_version.to_owned(),
+            message,
+        }),
+        code @ (STATUS_BAD_SIG | ERRNO_LOGIN_EXPIRED) => Err(Error::StaleUserKey { code, message }),
+        code => Err(Error::Api { code, message }),
+    }
+}
+
+#[cfg(any(feature = "blocking", feature = "async"))]
+pub(crate) fn check_create_dir(res: CreateDirResponseJson) -> Result<u64> {
+    if !res.state {
+        return Err(webapi_error(res.errno, res.error));
+    }
+    match res.cid {
+        Some(cid) => cid
+            .code()
+            .and_then(|cid| u64::try_from(cid).ok())
+            .ok_or_else(|| Error::decode("invalid cid", &format!("{:?}", cid))),
+        None => Err(Error::decode("missing cid", &format!("{:?}", res.other))),
+    }
+}
+
+/// The cid of the folder at `path`, 115 answers id 0 when there is none.
+#[cfg(any(feature = "blocking", feature = "async"))]
+pub(crate) fn check_folder_id(res: FolderIdResponseJson, path: &str) -> Result<u64> {
+    if !res.state {
+        return Err(webapi_error(res.errno, res.error));
+    }
+    let id = res.id.as_ref().and_then(StringOri32::code);
+    match id.and_then(|id| u64::try_from(id).ok()) {
+        Some(0) => Err(Error::FolderNotFound(path.to_owned())),
+        Some(cid) => Ok(cid),
+        None => Err(Error::decode("invalid folder id", &format!("{:?}", res.id))),
+    }
+}
+
+/// The cid and name of the folders of a listing page, and how many
+/// entries the listing has in all.
+#[cfg(any(feature = "blocking", feature = "async"))]
+pub(crate) fn check_list_folders(res: ListResponseJson) -> Result<(Vec<(u64, String)>, usize)> {
+    if !res.state {
+        return Err(webapi_error(res.errno, res.error));
+    }
+    let mut folders = Vec::with_capacity(res.data.len());
+    for entry in res.data {
+        let cid = entry.cid.as_ref().and_then(StringOri32::code);
+        match cid.and_then(|cid| u64::try_from(cid).ok()) {
+            Some(cid) => folders.push((cid, entry.n)),
+            None => return Err(Error::decode("invalid folder cid", &format!("{:?}", entry))),
+        }
+    }
+    let count = res.count.as_ref().and_then(StringOri32::code).unwrap_or(0);
+    Ok((folders, usize::try_from(count).unwrap_or(0)))
+}
+
+#[cfg(any(feature = "blocking", feature = "async"))]
+pub(crate) fn check_delete(res: DeleteResponseJson) -> MayBeError {
+    if res.state {
+        Ok(())
+    } else {
+        Err(webapi_error(res.errno, res.error))
+    }
+}
+
+/// User name, vip state and vip expire time. The login has expired for the
+/// profile when the cookies are of another user than `user_id`.
+#[cfg(any(feature = "blocking", feature = "async"))]
+pub(crate) fn check_nav(
+    res: NavResponseJson,
+    user_id: &str,
+) -> Result<(String, bool, Option<i64>)> {
+    if !res.state {
+        return Err(match res.errno {
+            None => Error::AuthExpired,
+            errno => webapi_error(errno, res.error),
+        });
+    }
+    let data = res
+        .data
+        .ok_or_else(|| Error::decode("missing data", &format!("{:?}", res.other)))?;
+    let nav_user_id = data.user_id.as_ref().and_then(StringOri32::code);
+    if nav_user_id.is_some_and(|id| id.to_string() != user_id) {
+        return Err(Error::AuthExpired);
+    }
+    let vip = data.vip.as_ref().and_then(StringOri32::code).unwrap_or(0) != 0;
+    let expire = data
+        .expire
+        .as_ref()
+        .and_then(StringOri32::code)
+        .filter(|t| *t > 0);
+    Ok((data.user_name.unwrap_or_default(), vip, expire))
+}
+
+/// Total and used space in bytes.
+#[cfg(any(feature = "blocking", feature = "async"))]
+pub(crate) fn check_space_info(res: SpaceInfoResponseJson) -> Result<(u64, u64)> {
+    if !res.state {
+        return Err(webapi_error(res.errno, res.error));
+    }
+    let space = match res.data {
+        Some(data) => data.space_info,
+        None => return Err(Error::decode("missing data", &format!("{:?}", res.other))),
+    };
+    let size = |s: &SpaceSize| {
+        s.size
+            .code()
+            .and_then(|n| u64::try_from(n).ok())
+            .ok_or_else(|| Error::decode("invalid size", &format!("{:?}", s.size)))
+    };
+    Ok((size(&space.all_total)?, size(&space.all_use)?))
+}
+
+#[cfg(feature = "blocking")]
+pub(crate) fn check_qr_token(res: QrResponseJson<QrToken>) -> Result<QrToken> {
+    qr_data(res)
+}
+
+#[cfg(feature = "blocking")]
+pub(crate) fn check_qr_status(res: QrResponseJson<QrStatusData>) -> Result<QrStatus> {
+    let data = qr_data(res)?;
+    let code = data.status.unwrap_or_default();
+    QrStatus::from_code(code)
+        .ok_or_else(|| Error::decode("unknown qr status", &format!("{} {:?}", code, data.other)))
+}
+
+/// The cookie header 115 hands out for a confirmed qr code.
+#[cfg(feature = "blocking")]
+pub(crate) fn check_qr_login(res: QrResponseJson<QrLoginData>) -> Result<String> {
+    let mut cookie = qr_data(res)?.cookie;
+    if cookie.is_empty() {
+        return Err(Error::decode("missing cookie", ""));
+    }
+    // the ones 115 checks first, the rest in name order
+    let mut pairs: Vec<_> = crate::cookies::REQUIRED_COOKIES
+        .iter()
+        .filter_map(|name| cookie.remove_entry(*name))
+        .collect();
+    pairs.extend(cookie);
+    let pairs: Vec<_> = pairs
+        .into_iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect();
+    Ok(pairs.join("; "))
+}
+
+#[cfg(feature = "blocking")]
+fn qr_data<T: std::fmt::Debug>(res: QrResponseJson<T>) -> Result<T> {
+    let ok = res.state.as_i64() == Some(1) || res.state.as_bool() == Some(true);
+    match res.data {
+        Some(data) if ok => Ok(data),
+        None if ok => Err(Error::decode("missing data", &format!("{:?}", res.other))),
+        _ => Err(Error::Api {
+            code: res.code.unwrap_or(-1),
+            message: res.message.js_utf8_decode(),
+        }),
+    }
+}
+
+#[cfg(feature = "blocking")]
+pub(crate) fn qr_status_query(token: &QrToken) -> [(&'static str, String); 3] {
+    [
+        ("uid", token.uid.clone()),
+        ("time", token.time.to_string()),
+        ("sign", token.sign.clone()),
+    ]
+}
+
+#[cfg(feature = "blocking")]
+pub(crate) fn qr_login_form(token: &QrToken) -> [(&'static str, String); 2] {
+    [("account", token.uid.clone()), ("app", "web".to_owned())]
+}
+
+#[cfg(any(feature = "blocking", feature = "async"))]
+fn webapi_error(errno: Option<StringOri32>, error: Option<String>) -> Error {
+    match errno.as_ref().and_then(StringOri32::code) {
+        Some(ERRNO_DIR_EXIST) => Error::DirExist,
+        Some(ERRNO_LOGIN_EXPIRED) => Error::AuthExpired,
+        Some(ERRNO_VERIFY_REQUIRED) => Error::VerificationRequired {
+            message: error.unwrap_or_default().js_utf8_decode(),
+        },
+        code => Error::Api {
+            code: code.unwrap_or(-1),
+            message: error.unwrap_or_default().js_utf8_decode(),
+        },
+    }
+}
+
+#[cfg(any(feature = "blocking", feature = "async"))]
+trait JsUnicodeEncoded {
+    fn js_utf8_decode(&self) -> String;
+}
+
+#[cfg(any(feature = "blocking", feature = "async"))]
+impl JsUnicodeEncoded for String {
+    /// Messages that fail to unescape are kept as is.
+    fn js_utf8_decode(&self) -> String {
+        unescape::unescape(self).unwrap_or_else(|| self.to_owned())
+    }
+}
+
+pub type Other = std::collections::BTreeMap<String, Value>;
+/// `app/uploadinfo` response.
+#[derive(Deserialize, Debug)]
+pub struct UserInfo {
+    pub state: Option<bool>,
+    pub user_id: Option<i64>,
+    pub userkey: Option<String>,
+    #[serde(flatten)]
+    pub other: Other,
+}
+
+/// `initupload.php` response, `status` 2 means rapid upload done.
+#[derive(Deserialize, Debug)]
+pub struct UploadResponseJson {
+    pub statuscode: i64,
+    #[serde(default)]
+    pub statusmsg: String,
+    pub status: Option<i64>,
+    #[serde(flatten)]
+    pub other: Other,
+}
+
+/// `files/add` response.
+#[derive(Deserialize, Debug)]
+pub struct CreateDirResponseJson {
+    pub state: bool,
+    pub errno: Option<StringOri32>,
+    pub error: Option<String>,
+    pub cid: Option<StringOri32>,
+    #[serde(flatten)]
+    pub other: Other,
+}
+
+/// `files/getid` response.
+#[derive(Deserialize, Debug)]
+pub struct FolderIdResponseJson {
+    pub state: bool,
+    pub errno: Option<StringOri32>,
+    pub error: Option<String>,
+    pub id: Option<StringOri32>,
+    #[serde(flatten)]
+    pub other: Other,
+}
+
+/// `files` listing response.
+#[derive(Deserialize, Debug)]
+pub struct ListResponseJson {
+    pub state: bool,
+    pub errno: Option<StringOri32>,
+    pub error: Option<String>,
+    /// entries in the folder, over every page
+    pub count: Option<StringOri32>,
+    #[serde(default)]
+    pub data: Vec<ListEntryJson>,
+    #[serde(flatten)]
+    pub other: Other,
+}
+
+/// A folder of a `files` listing.
+#[derive(Deserialize, Debug)]
+pub struct ListEntryJson {
+    pub cid: Option<StringOri32>,
+    /// name
+    #[serde(default)]
+    pub n: String,
+    #[serde(flatten)]
+    pub other: Other,
+}
+
+/// webapi sends `errno` and `cid` either as a string or a number.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum StringOri32 {
+    S(String),
+    Number(i64),
+}
+
+impl StringOri32 {
+    pub fn code(&self) -> Option<i64> {
+        match self {
+            StringOri32::S(s) => s.parse().ok(),
+            StringOri32::Number(n) => Some(*n),
+        }
+    }
+}
+
+/// `rb/delete` response.
+#[derive(Deserialize, Debug)]
+pub struct DeleteResponseJson {
+    pub state: bool,
+    pub errno: Option<StringOri32>,
+    pub error: Option<String>,
+    #[serde(flatten)]
+    pub other: Other,
+}
+
+/// `?ct=ajax&ac=nav` response of my.115.com.
+#[derive(Deserialize, Debug)]
+pub struct NavResponseJson {
+    pub state: bool,
+    pub errno: Option<StringOri32>,
+    pub error: Option<String>,
+    pub data: Option<NavData>,
+    #[serde(flatten)]
+    pub other: Other,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct NavData {
+    pub user_id: Option<StringOri32>,
+    pub user_name: Option<String>,
+    /// non zero for vip accounts
+    pub vip: Option<StringOri32>,
+    /// unix time the vip membership ends
+    pub expire: Option<StringOri32>,
+    #[serde(flatten)]
+    pub other: Other,
+}
+
+/// `files/index_info` response.
+#[derive(Deserialize, Debug)]
+pub struct SpaceInfoResponseJson {
+    pub state: bool,
+    pub errno: Option<StringOri32>,
+    pub error: Option<String>,
+    pub data: Option<SpaceInfoData>,
+    #[serde(flatten)]
+    pub other: Other,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SpaceInfoData {
+    pub space_info: SpaceInfo,
+}
+
+/// Sizes in bytes.
+#[derive(Deserialize, Debug)]
+pub struct SpaceInfo {
+    pub all_total: SpaceSize,
+    pub all_use: SpaceSize,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SpaceSize {
+    pub size: StringOri32,
+}
+
+/// What `status` reports about the logged in account.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountInfo {
+    pub user_id: String,
+    pub user_name: String,
+    pub vip: bool,
+    /// unix time the vip membership ends
+    pub vip_expire: Option<i64>,
+    /// bytes
+    pub space_total: u64,
+    /// bytes
+    pub space_used: u64,
+    /// the cached `user_key` is still the one 115 hands out
+    pub user_key_valid: bool,
+}
+
+impl std::fmt::Debug for AccountInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AccountInfo")
+            .field("user_id", &redact::MASK)
+            .field("user_name", &self.user_name)
+            .field("vip", &self.vip)
+            .field("vip_expire", &self.vip_expire)
+            .field("space_total", &self.space_total)
+            .field("space_used", &self.space_used)
+            .field("user_key_valid", &self.user_key_valid)
+            .finish()
+    }
+}
+
+/// A login qr code, valid for a few minutes.
+#[derive(Deserialize, Clone, PartialEq, Eq)]
+pub struct QrToken {
+    pub uid: String,
+    pub time: i64,
+    pub sign: String,
+    /// the text to encode in the qr code
+    pub qrcode: String,
+}
+
+/// Anyone with the token can log in once it is confirmed.
+impl std::fmt::Debug for QrToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("QrToken")
+            .field("uid", &redact::MASK)
+            .field("time", &self.time)
+            .field("sign", &redact::MASK)
+            .field("qrcode", &redact::MASK)
+            .finish()
+    }
+}
+
+/// Scan state of a login qr code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QrStatus {
+    Waiting,
+    /// scanned, waiting for the confirmation on the phone
+    Scanned,
+    Confirmed,
+    Expired,
+    Canceled,
+}
+
+impl QrStatus {
+    #[cfg(feature = "blocking")]
+    fn from_code(code: i64) -> Option<Self> {
+        match code {
+            0 => Some(QrStatus::Waiting),
+            1 => Some(QrStatus::Scanned),
+            2 => Some(QrStatus::Confirmed),
+            -1 => Some(QrStatus::Expired),
+            -2 => Some(QrStatus::Canceled),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for QrStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QrStatus::Waiting => write!(f, "waiting for the scan"),
+            QrStatus::Scanned => write!(f, "scanned, confirm the login on the phone"),
+            QrStatus::Confirmed => write!(f, "login confirmed"),
+            QrStatus::Expired => write!(f, "qr code expired"),
+            QrStatus::Canceled => write!(f, "login canceled on the phone"),
+        }
+    }
+}
+
+/// qrcodeapi / passportapi response, `state` is 1 on success.
+#[derive(Deserialize, Debug)]
+pub struct QrResponseJson<T> {
+    pub state: Value,
+    pub code: Option<i64>,
+    #[serde(default)]
+    pub message: String,
+    pub data: Option<T>,
+    #[serde(flatten)]
+    pub other: Other,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct QrStatusData {
+    pub status: Option<i64>,
+    #[serde(flatten)]
+    pub other: Other,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct QrLoginData {
+    #[serde(default)]
+    pub cookie: std::collections::BTreeMap<String, String>,
+    #[serde(flatten)]
+    pub other: Other,
+}
+
+fn sha1(content: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.input_str(content);
+    hasher.result_str()
+}
@@ -0,0 +1,484 @@
+//! Async counterpart of [`crate::upload::Session`] on the non-blocking reqwest client.
+//!
+//! Signing, forms and response checks are shared with the blocking session
+//! through [`crate::api`], so both behave the same against 115.
+use reqwest::{Client, RequestBuilder, Response};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::Instrument;
+
+use crate::api::*;
+use crate::client::ClientIdentity;
+use crate::endpoints::Endpoints;
+use crate::error::{Error, Result};
+use crate::network::configure;
+use crate::ratelimit::{ApiCall, RateLimiter};
+use crate::redact;
+use crate::retry::RetryPolicy;
+
+type MayBeError = Result<()>;
+
+impl SessionBuilder {
+    pub fn build_async(mut self) -> Result<AsyncSession> {
+        let limiter = self.take_limiter();
+        let config = self.config;
+        let ua = config.user_agent();
+        let client = configure(Client::builder(), &config)?.build()?;
+
+        Ok(AsyncSession {
+            cookies: self.cookies,
+            keys: UserKeys::new(self.keys),
+            client,
+            ua,
+            identity: config.client,
+            endpoints: config.endpoints,
+            retry: config.retry,
+            retries: AtomicU64::new(0),
+            key_refreshes: AtomicU64::new(0),
+            limiter,
+        })
+    }
+}
+
+/// A logged in 115 account on the async client.
+///
+/// Every call but `get_user_key` takes `&self`, so one session (in an `Arc`)
+/// can drive many requests concurrently under one runtime.
+///
+/// ```no_run
+/// use rs115::{AsyncSession, Endpoints};
+///
+/// # async fn run() -> rs115::Result<()> {
+/// let mut session = AsyncSession::builder("UID=...; CID=...; SEID=...")
+///     .endpoints(Endpoints::default())
+///     .build_async()?;
+/// session.get_key_if_none().await?;
+/// let cid = session.create_folder(0, "new folder".into()).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Serialize, Deserialize)]
+pub struct AsyncSession {
+    pub(crate) cookies: String,
+    #[serde(flatten)]
+    pub(crate) keys: UserKeys,
+
+    #[serde(skip)]
+    pub(crate) client: Client,
+    #[serde(skip)]
+    pub(crate) ua: String,
+    /// `None` for the default client
+    #[serde(skip)]
+    pub(crate) identity: Option<ClientIdentity>,
+    #[serde(skip)]
+    pub(crate) endpoints: Endpoints,
+    #[serde(skip)]
+    pub(crate) retry: RetryPolicy,
+    #[serde(skip)]
+    pub(crate) retries: AtomicU64,
+    #[serde(skip)]
+    pub(crate) key_refreshes: AtomicU64,
+    #[serde(skip)]
+    pub(crate) limiter: Arc<RateLimiter>,
+}
+
+/// Cookies and user keys masked, see [`crate::redact`].
+impl std::fmt::Debug for AsyncSession {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AsyncSession")
+            .field("cookies", &redact::cookies(&self.cookies))
+            .field("keys", &self.keys)
+            .field("ua", &self.ua)
+            .field("identity", &self.identity)
+            .field("endpoints", &self.endpoints)
+            .field("retry", &self.retry)
+            .field("retries", &self.retries)
+            .field("key_refreshes", &self.key_refreshes)
+            .field("limiter", &self.limiter)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AsyncSession {
+    pub fn builder(cookies: impl Into<String>) -> SessionBuilder {
+        SessionBuilder::new(cookies)
+    }
+
+    /// Rebuild a deserialized session (which has no http client) with `config`.
+    pub fn restore(self, config: SessionConfig) -> Result<AsyncSession> {
+        let builder = SessionBuilder::new(self.cookies).config(config);
+        match self.keys.get() {
+            Some((id, key)) => builder.user_key(id, key).build_async(),
+            None => builder.build_async(),
+        }
+    }
+
+    pub fn cookies(&self) -> &str {
+        &self.cookies
+    }
+
+    pub fn user_id(&self) -> Option<String> {
+        self.keys.user_id()
+    }
+
+    pub fn user_key(&self) -> Option<String> {
+        self.keys.user_key()
+    }
+
+    /// The client this session presents itself as.
+    pub fn client_identity(&self) -> ClientIdentity {
+        self.identity.clone().unwrap_or_default()
+    }
+
+    pub fn endpoints(&self) -> &Endpoints {
+        &self.endpoints
+    }
+
+    /// The limiter every call of this session goes through.
+    pub fn rate_limiter(&self) -> Arc<RateLimiter> {
+        Arc::clone(&self.limiter)
+    }
+
+    /// Retries done so far by this session, over all calls.
+    pub fn retry_count(&self) -> u64 {
+        self.retries.load(Ordering::Relaxed)
+    }
+
+    /// Times the user key was fetched again after 115 rejected it.
+    pub fn key_refreshes(&self) -> u64 {
+        self.key_refreshes.load(Ordering::Relaxed)
+    }
+
+    /// Await `call()` until it succeeds, fails with a non retryable error or
+    /// runs out of attempts, sleeping as the retry policy says in between.
+    /// Every attempt waits for the rate limiter first, which rate-limit
+    /// answers slow down as in `Session`.
+    async fn with_retry<T, F, Fut>(&self, api: ApiCall, mut call: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let span = tracing::debug_span!("call", endpoint = api.endpoint());
+        async move {
+            let mut attempt = 1;
+            loop {
+                let wait = self.limiter.reserve(api);
+                if !wait.is_zero() {
+                    tokio::time::sleep(wait).await;
+                }
+                let started = Instant::now();
+                let res = call().await;
+                trace_attempt(attempt, started, &res);
+                let e = match res {
+                    Ok(v) => {
+                        self.limiter.recover();
+                        return Ok(v);
+                    }
+                    Err(e) => e,
+                };
+                let delay = self.retry.delay(attempt);
+                let rate_limited = self.retry.is_rate_limited(&e);
+                if rate_limited {
+                    self.limiter.slow_down(delay);
+                }
+                if attempt >= self.retry.max_attempts || !self.retry.is_retryable(&e) {
+                    return Err(e);
+                }
+                if !rate_limited {
+                    tokio::time::sleep(delay).await;
+                }
+                self.retries.fetch_add(1, Ordering::Relaxed);
+                attempt += 1;
+            }
+        }
+        .instrument(span)
+        .await
+    }
+
+    /// Fetch `user_id`/`user_key` unless already known, required before uploading.
+    pub async fn get_key_if_none(&mut self) -> MayBeError {
+        if self.keys.get().is_none() {
+            self.get_user_key().await?
+        }
+        Ok(())
+    }
+
+    /// Ask `app/uploadinfo` for the `user_id`/`user_key` used to sign uploads.
+    pub async fn get_user_key(&mut self) -> MayBeError {
+        let (user_id, user_key) = self.fetch_user_key().await?;
+        self.keys.set(user_id, user_key);
+
+        Ok(())
+    }
+
+    /// Replace a user key 115 rejected.
+    async fn refresh_user_key(&self) -> MayBeError {
+        let (user_id, user_key) = self.fetch_user_key().await?;
+        self.keys.set(user_id, user_key);
+        self.key_refreshes.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    async fn fetch_user_key(&self) -> Result<(String, String)> {
+        self.with_retry(ApiCall::UserInfo, || async move {
+            let url = self.endpoints.info_url();
+            let request = self
+                .client
+                .get(url)
+                .header("User-Agent", &self.ua)
+                .header("Cookie", &self.cookies);
+            let res = send(&self.client, request).await?;
+
+            check_user_info(parse_json(res).await?)
+        })
+        .await
+    }
+
+    /// Ask 115 who the cookies belong to, their vip state and space, and
+    /// whether the cached `user_key` is still valid. Nothing is cached.
+    /// Cookies of another user than the cached one are an expired login.
+    pub async fn account_info(&self) -> Result<AccountInfo> {
+        let (user_id, user_key) = self.fetch_user_key().await?;
+        let cached_id = &self.keys.user_id().unwrap_or_else(|| user_id.clone());
+        let (user_name, vip, vip_expire) = self
+            .with_retry(ApiCall::Nav, || async move {
+                let request = self
+                    .client
+                    .get(self.endpoints.nav_url())
+                    .header("User-Agent", &self.ua)
+                    .header("Cookie", &self.cookies);
+                let res = send(&self.client, request).await?;
+
+                check_nav(parse_json(res).await?, cached_id)
+            })
+            .await?;
+        let (space_total, space_used) = self
+            .with_retry(ApiCall::SpaceInfo, || async move {
+                let request = self
+                    .client
+                    .get(self.endpoints.space_info_url())
+                    .header("User-Agent", &self.ua)
+                    .header("Cookie", &self.cookies);
+                let res = send(&self.client, request).await?;
+
+                check_space_info(parse_json(res).await?)
+            })
+            .await?;
+
+        Ok(AccountInfo {
+            user_key_valid: self.keys.get() == Some((user_id.clone(), user_key.clone())),
+            user_id,
+            user_name,
+            vip,
+            vip_expire,
+            space_total,
+            space_used,
+        })
+    }
+
+    /// Rapid-upload `req` by hash, `Ok` only when 115 already had the file (status 2).
+    pub async fn rapid_upload(&self, req: &RapidUploadRequest) -> MayBeError {
+        self.upload115_sha1(
+            req.filename.clone(),
+            req.file_size.to_string(),
+            req.sha1.clone(),
+            req.block_sha1.clone(),
+            req.cid,
+        )
+        .await
+    }
+
+    /// Upload by hash. A stale user key is fetched again and the upload
+    /// retried once with it.
+    pub async fn upload115_sha1(
+        &self,
+        filename: String,
+        file_size: String,
+        total_hash: String,
+        block_hash: String,
+        cid: u64,
+    ) -> MayBeError {
+        let upload = || {
+            self.signed_upload(
+                filename.clone(),
+                file_size.clone(),
+                total_hash.clone(),
+                block_hash.clone(),
+                cid,
+            )
+        };
+        match upload().await {
+            Err(Error::StaleUserKey { .. }) => {
+                tracing::info!("user key rejected, fetching a new one");
+                self.refresh_user_key().await?;
+                upload().await
+            }
+            res => res,
+        }
+    }
+
+    async fn signed_upload(
+        &self,
+        filename: String,
+        file_size: String,
+        total_hash: String,
+        block_hash: String,
+        cid: u64,
+    ) -> MayBeError {
+        let (user_id, user_key) = self.keys.get().ok_or(Error::MissingUserKey)?;
+        let client = self.client_identity();
+        let (url, param) = upload_request(
+            &self.endpoints,
+            &client,
+            &user_id,
+            &user_key,
+            filename,
+            file_size,
+            total_hash,
+            block_hash,
+            cid,
+        );
+
+        let (url, param, app_version) = (&url, &param, &client.app_version);
+        self.with_retry(ApiCall::InitUpload, || async move {
+            let request = self
+                .client
+                .post(url)
+                .header("Content-Type", "application/x-www-form-urlencoded")
+                .header("Cookie", &self.cookies)
+                .header("User-Agent", &self.ua)
+                .form(param);
+            let res = send(&self.client, request).await?;
+
+            check_upload(parse_json(res).await?, app_version)
+        })
+        .await
+    }
+
+    /// Create folder `name` under `pid`, returns the new cid. When a retry
+    /// finds the folder there, the attempt before made it and its cid is
+    /// returned.
+    pub async fn create_folder(&self, pid: u64, name: String) -> Result<u64> {
+        let param = &create_dir_form(pid, name.clone());
+        let url = &self.endpoints.create_dir_url();
+        let mut attempts = 0;
+        let res = self
+            .with_retry(ApiCall::CreateDir, || {
+                attempts += 1;
+                async move {
+                    let request = self
+                        .client
+                        .post(url)
+                        .form(param)
+                        .header("User-Agent", &self.ua)
+                        .header("Cookie", &self.cookies)
+                        .header("Content-Type", "application/x-www-form-urlencoded");
+                    let res = send(&self.client, request).await?;
+
+                    check_create_dir(parse_json(res).await?)
+                }
+            })
+            .await;
+        match res {
+            // an attempt whose answer was lost may have made the folder
+            Err(Error::DirExist) if attempts > 1 => {
+                self.find_folder(pid, &name).await?.ok_or(Error::DirExist)
+            }
+            res => res,
+        }
+    }
+
+    /// The cid of the folder at `path`, like `/movies/2024`; `/` is 0.
+    pub async fn folder_id(&self, path: &str) -> Result<u64> {
+        let path = path.trim_end_matches('/');
+        if path.is_empty() {
+            return Ok(0);
+        }
+        let query = &folder_id_query(path);
+        let url = &self.endpoints.folder_id_url();
+        self.with_retry(ApiCall::FolderId, || async move {
+            let request = self
+                .client
+                .get(url)
+                .query(query)
+                .header("User-Agent", &self.ua)
+                .header("Cookie", &self.cookies);
+            let res = send(&self.client, request).await?;
+
+            check_folder_id(parse_json(res).await?, path)
+        })
+        .await
+    }
+
+    /// The cid of the folder `name` in `pid`, if there is one.
+    pub async fn find_folder(&self, pid: u64, name: &str) -> Result<Option<u64>> {
+        let url = &self.endpoints.list_url();
+        let mut offset = 0;
+        loop {
+            let query = &list_folders_query(pid, offset);
+            let (folders, count) = self
+                .with_retry(ApiCall::List, || async move {
+                    let request = self
+                        .client
+                        .get(url)
+                        .query(query)
+                        .header("User-Agent", &self.ua)
+                        .header("Cookie", &self.cookies);
+                    let res = send(&self.client, request).await?;
+
+                    check_list_folders(parse_json(res).await?)
+                })
+                .await?;
+            if let Some(&(cid, _)) = folders.iter().find(|(_, n)| n == name) {
+                return Ok(Some(cid));
+            }
+            offset += folders.len();
+            if folders.is_empty() || offset >= count {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Move `target` in folder `pid` to the recycle bin.
+    pub async fn delete_one(&self, pid: u64, target: u64) -> MayBeError {
+        self.delete_bulk(pid, vec![target]).await
+    }
+
+    pub async fn delete_bulk(&self, pid: u64, target_list: Vec<u64>) -> MayBeError {
+        let param = &delete_form(pid, target_list);
+        let url = &self.endpoints.delete_url();
+        self.with_retry(ApiCall::Delete, || async move {
+            let request = self
+                .client
+                .post(url)
+                .form(param)
+                .header("User-Agent", &self.ua)
+                .header("Cookie", &self.cookies)
+                .header("Content-Type", "application/x-www-form-urlencoded");
+            let res = send(&self.client, request).await?;
+
+            check_delete(parse_json(res).await?)
+        })
+        .await
+    }
+}
+
+/// Build and send `request`, tracing it with the secrets masked.
+async fn send(client: &Client, request: RequestBuilder) -> Result<Response> {
+    let request = request.build()?;
+    trace_request(
+        request.method().as_str(),
+        request.url().as_str(),
+        request.body().and_then(|body| body.as_bytes()),
+    );
+    Ok(client.execute(request).await?)
+}
+
+async fn parse_json<T: DeserializeOwned>(res: Response) -> Result<T> {
+    let status = res.status().as_u16();
+    let body = res.text().await?;
+    parse_body(status, &body)
+}
@@ -1,18 +1,20 @@
 #![allow(dead_code)]
-pub(crate) fn is_valid_hash(content: &str) -> bool {
-    matches!(content.len(), 32 | 40) && content.chars().all(|c| c.is_digit(16))
+/// 32 or 40 hex chars, e.g. the btih of a magnet link.
+pub fn is_valid_hash(content: &str) -> bool {
+    matches!(content.len(), 32 | 40) && content.chars().all(|c| c.is_ascii_hexdigit())
 }
-pub(crate) fn is_valid_sha1_hex(content: &str) -> bool {
-    content.len() == 40 && content.chars().all(|c| c.is_digit(16))
+pub fn is_valid_sha1_hex(content: &str) -> bool {
+    content.len() == 40 && content.chars().all(|c| c.is_ascii_hexdigit())
 }
-pub(crate) fn is_valid_hex(content: &str) -> bool {
-    content.chars().all(|c| c.is_digit(16))
+pub fn is_valid_hex(content: &str) -> bool {
+    content.chars().all(|c| c.is_ascii_hexdigit())
 }
 
-pub(crate) fn is_valid_sha1_line(content: &str) -> bool {
-    if content.starts_with("115://") && (content.matches('|').count() > 3) {
+/// `115://name|size|sha1|blocksha1` with a numeric size and two sha1s.
+pub fn is_valid_sha1_line(content: &str) -> bool {
+    if content.starts_with("115://") && (content.matches('|').count() >= 3) {
         let res: Vec<&str> = content.split('|').collect();
-        return res[1].chars().all(|x| x.is_digit(10))
+        return res[1].chars().all(|x| x.is_ascii_digit())
             && is_valid_sha1_hex(res[2])
             && is_valid_sha1_hex(res[3]);
     }
@@ -60,6 +62,16 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn sha1_line_test() {
+        assert!(is_valid_sha1_line("115://a.iso|19880869888|702C4E22BE8F3D856C496178C488E86B606D9912|13F48115A678499823003C8331E9C0AD0243F089"));
+        // trailing fields some tools add
+        assert!(is_valid_sha1_line("115://a.iso|5|702C4E22BE8F3D856C496178C488E86B606D9912|13F48115A678499823003C8331E9C0AD0243F089|extra"));
+        assert!(!is_valid_sha1_line(
+            "115://a.iso|5|702C4E22BE8F3D856C496178C488E86B606D9912"
+        ));
+    }
+
     #[test]
     fn sha1_link_without_protocol_test() {
         let line_without_protocol = "[座头鲸 Humpback Whales 2015][3D+2D][无中字][18.52GB].iso|19880869888|702C4E22BE8F3D856C496178C488E86B606D9912|13F48115A678499823003C8331E9C0AD0243F089";
@@ -0,0 +1,93 @@
+//! The 115 client `rs115` presents itself as.
+//!
+//! `initupload.php` wants the app version of the client making the upload,
+//! and 115 stops accepting old versions now and then. The identity is picked
+//! from named presets, per profile, so a retired version only needs
+//! `profiles client <newer preset>` instead of a new release.
+use serde::{Deserialize, Serialize};
+
+/// Preset used when neither the profile nor the config names one.
+pub const DEFAULT_CLIENT: &str = "desktop-29";
+
+/// Name, app version, appid and isp of the known clients, oldest first.
+const PRESETS: &[(&str, &str, u32, u32)] = &[
+    ("desktop-29", "29.0.0", 0, 0),
+    ("desktop-30", "30.5.1", 0, 0),
+    ("desktop-35", "35.6.0", 0, 0),
+];
+
+const USER_AGENT_PREFIX: &str = r"Mozilla/5.0 115disk/";
+
+/// App version, user agent, appid and isp sent to 115.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientIdentity {
+    /// `app_ver` and `appversion` of uploads
+    pub app_version: String,
+    pub user_agent: String,
+    pub appid: u32,
+    pub isp: u32,
+}
+
+impl Default for ClientIdentity {
+    fn default() -> Self {
+        Self::preset(DEFAULT_CLIENT).expect("the default client is a preset")
+    }
+}
+
+impl ClientIdentity {
+    /// The 115 desktop client `app_version`.
+    pub fn desktop(app_version: impl Into<String>) -> Self {
+        let app_version = app_version.into();
+        Self {
+            user_agent: format!("{}{}", USER_AGENT_PREFIX, app_version),
+            app_version,
+            appid: 0,
+            isp: 0,
+        }
+    }
+
+    pub fn preset(name: &str) -> Option<Self> {
+        PRESETS
+            .iter()
+            .find(|preset| preset.0 == name)
+            .map(|&(_, app_version, appid, isp)| Self {
+                appid,
+                isp,
+                ..Self::desktop(app_version)
+            })
+    }
+
+    /// Names of the presets, oldest first.
+    pub fn preset_names() -> impl Iterator<Item = &'static str> {
+        PRESETS.iter().map(|preset| preset.0)
+    }
+
+    /// Name of the newest preset, the one to try when 115 retired a version.
+    pub fn newest_preset() -> &'static str {
+        PRESETS.last().expect("presets are not empty").0
+    }
+
+    /// Name of the preset this identity is, `None` if customised.
+    pub fn preset_name(&self) -> Option<&'static str> {
+        Self::preset_names().find(|name| Self::preset(name).as_ref() == Some(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_test() {
+        let client = ClientIdentity::default();
+        assert_eq!(client.app_version, "29.0.0");
+        assert_eq!(client.user_agent, "Mozilla/5.0 115disk/29.0.0");
+        assert_eq!(client.preset_name(), Some(DEFAULT_CLIENT));
+        assert!(ClientIdentity::preset("desktop-1").is_none());
+
+        let newest = ClientIdentity::preset(ClientIdentity::newest_preset()).unwrap();
+        assert_ne!(newest, client);
+        let custom = ClientIdentity { isp: 1, ..newest };
+        assert_eq!(custom.preset_name(), None);
+    }
+}
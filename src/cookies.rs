@@ -0,0 +1,208 @@
+//! Parsing and validation of the login cookies copied from a browser.
+use std::str::FromStr;
+
+/// Cookies 115 needs to accept a request as logged in.
+pub const REQUIRED_COOKIES: [&str; 3] = ["UID", "CID", "SEID"];
+
+/// The `name=value` pairs of a `Cookie` header, in their original order.
+///
+/// Displays as the normalised header `UID=...; CID=...; SEID=...`, its
+/// `Debug` masks the values.
+#[derive(Clone, PartialEq, Eq)]
+pub struct CookieJar {
+    pairs: Vec<(String, String)>,
+}
+
+impl std::fmt::Debug for CookieJar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("CookieJar")
+            .field(&crate::redact::cookies(&self.to_string()))
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CookieError {
+    Empty,
+    /// a part without `=` or with an empty name
+    Malformed(String),
+    /// quotes, control characters or whitespace inside a name or value
+    InvalidChar(String),
+    /// the same cookie twice with different values
+    Conflict(String),
+    Missing(Vec<&'static str>),
+}
+
+impl std::fmt::Display for CookieError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CookieError::Empty => write!(f, "cookies are empty"),
+            CookieError::Malformed(part) => {
+                write!(f, "\"{}\" is not a name=value cookie", part)
+            }
+            CookieError::InvalidChar(name) => write!(
+                f,
+                "cookie {} contains quotes, spaces or control characters",
+                name
+            ),
+            CookieError::Conflict(name) => {
+                write!(f, "cookie {} is set twice with different values", name)
+            }
+            CookieError::Missing(names) => {
+                write!(f, "missing cookies: {}", names.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for CookieError {}
+
+impl CookieJar {
+    /// Parse a `Cookie` header as copied from the browser, returning the
+    /// jar and warnings about what was fixed up on the way.
+    ///
+    /// An optional `Cookie:` prefix, quotes around the whole string or
+    /// around single values and line breaks between pairs are accepted;
+    /// repeated pairs with the same value are merged with a warning.
+    pub fn parse(s: &str) -> Result<(Self, Vec<String>), CookieError> {
+        let mut warnings = Vec::new();
+        let mut s = s.trim();
+        if let Some(rest) = strip_prefix_ignore_case(s, "cookie:") {
+            s = rest.trim();
+        }
+        for quote in ['"', '\''] {
+            if s.len() > 1 && s.starts_with(quote) && s.ends_with(quote) {
+                warnings.push("removed the quotes around the cookies".to_owned());
+                s = s[1..s.len() - 1].trim();
+            }
+        }
+        if s.is_empty() {
+            return Err(CookieError::Empty);
+        }
+
+        let mut pairs: Vec<(String, String)> = Vec::new();
+        for part in s.split([';', '\n', '\r']) {
+            let part = part.trim();
+            if part.is_empty() {
+                continue;
+            }
+            let (name, value) = match part.split_once('=') {
+                Some((name, value)) if !name.trim().is_empty() => (name.trim(), value.trim()),
+                _ => return Err(CookieError::Malformed(part.to_owned())),
+            };
+            let value = match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+                Some(unquoted) => unquoted,
+                None => value,
+            };
+            if !is_token(name) || !is_token(value) {
+                return Err(CookieError::InvalidChar(name.to_owned()));
+            }
+            match pairs.iter().find(|(n, _)| n == name) {
+                Some((_, v)) if v == value => {
+                    warnings.push(format!("cookie {} is set twice, kept once", name))
+                }
+                Some(_) => return Err(CookieError::Conflict(name.to_owned())),
+                None => pairs.push((name.to_owned(), value.to_owned())),
+            }
+        }
+
+        let jar = Self { pairs };
+        let missing: Vec<_> = REQUIRED_COOKIES
+            .iter()
+            .copied()
+            .filter(|name| jar.get(name).is_none_or(str::is_empty))
+            .collect();
+        if !missing.is_empty() {
+            return Err(CookieError::Missing(missing));
+        }
+        Ok((jar, warnings))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.pairs
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.pairs.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+}
+
+impl FromStr for CookieJar {
+    type Err = CookieError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s).map(|(jar, _)| jar)
+    }
+}
+
+impl std::fmt::Display for CookieJar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, (name, value)) in self.pairs.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{}={}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+fn strip_prefix_ignore_case<'a>(s: &'a str, prefix: &str) -> Option<&'a str> {
+    match s.get(..prefix.len()) {
+        Some(head) if head.eq_ignore_ascii_case(prefix) => Some(&s[prefix.len()..]),
+        _ => None,
+    }
+}
+
+/// Printable ascii without the characters a cookie pair may not contain.
+fn is_token(s: &str) -> bool {
+    s.bytes()
+        .all(|b| b.is_ascii_graphic() && !matches!(b, b'"' | b'\'' | b',' | b';' | b'\\'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COOKIES: &str = "UID=1150000_A1_1650000000; CID=abcdef; SEID=0123456789";
+
+    #[test]
+    fn parse_and_normalise_test() {
+        let pasted = "Cookie: \"UID=1150000_A1_1650000000;CID=\"abcdef\";\n SEID=0123456789;\"\n";
+        let (jar, warnings) = CookieJar::parse(pasted).unwrap();
+        assert_eq!(jar.to_string(), COOKIES);
+        assert_eq!(jar.get("CID"), Some("abcdef"));
+        assert_eq!(warnings.len(), 1);
+
+        let (jar, warnings) =
+            CookieJar::parse(&format!("{}; CID=abcdef; acw_tc=1", COOKIES)).unwrap();
+        assert_eq!(jar.iter().count(), 4);
+        assert_eq!(warnings, ["cookie CID is set twice, kept once"]);
+    }
+
+    #[test]
+    fn invalid_cookies_test() {
+        let err = |s: &str| CookieJar::parse(s).unwrap_err();
+        assert_eq!(err("  \n"), CookieError::Empty);
+        assert_eq!(err("UID=1; CID"), CookieError::Malformed("CID".into()));
+        assert_eq!(
+            err("UID=1; CID=a\"b; SEID=2"),
+            CookieError::InvalidChar("CID".into())
+        );
+        assert_eq!(
+            err("UID=1; CID=a b; SEID=2"),
+            CookieError::InvalidChar("CID".into())
+        );
+        assert_eq!(
+            err("UID=1; UID=2; CID=a; SEID=b"),
+            CookieError::Conflict("UID".into())
+        );
+        assert_eq!(
+            err("UID=1; CID=; acw_tc=2"),
+            CookieError::Missing(vec!["CID", "SEID"])
+        );
+    }
+}
@@ -0,0 +1,179 @@
+//! Passphrase encryption of the cookie cache.
+//!
+//! scrypt derives a key from the passphrase and a random salt, chacha20-poly1305
+//! encrypts and authenticates the cache json with it. Every save draws a new
+//! salt and nonce, the scrypt cost is stored next to them.
+use base64::{engine::general_purpose::STANDARD, Engine};
+use crypto::aead::{AeadDecryptor, AeadEncryptor};
+use crypto::chacha20poly1305::ChaCha20Poly1305;
+use crypto::scrypt::{scrypt, ScryptParams};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+const CIPHER: &str = "scrypt-chacha20poly1305";
+/// Authenticated with the data, so the ciphertext can not pass as anything else.
+const AAD: &[u8] = b"rs115 cookie cache";
+/// scrypt N = 2^15, r = 8: 32MiB of memory per key.
+const DEFAULT_LOG_N: u8 = 15;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 8;
+const TAG_LEN: usize = 16;
+
+/// An encrypted cache file.
+#[derive(Debug, Serialize, Deserialize)]
+struct Sealed {
+    cipher: String,
+    log_n: u8,
+    r: u32,
+    p: u32,
+    salt: String,
+    nonce: String,
+    tag: String,
+    data: String,
+}
+
+/// The passphrase the cache is encrypted with.
+#[derive(Clone)]
+pub struct Encryption {
+    passphrase: String,
+    log_n: u8,
+}
+
+impl std::fmt::Debug for Encryption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Encryption")
+            .field("passphrase", &"***")
+            .field("log_n", &self.log_n)
+            .finish()
+    }
+}
+
+impl Encryption {
+    pub fn new(passphrase: impl Into<String>) -> Self {
+        Self {
+            passphrase: passphrase.into(),
+            log_n: DEFAULT_LOG_N,
+        }
+    }
+
+    /// scrypt cost of new saves as log2 of N, 1 to 20. Lower is faster and
+    /// weaker, meant for tests.
+    pub fn cost(mut self, log_n: u8) -> Self {
+        self.log_n = log_n.clamp(1, 20);
+        self
+    }
+
+    /// Encrypt `plain` into the json written to the cache file.
+    pub fn seal(&self, plain: &[u8]) -> String {
+        let mut salt = [0u8; SALT_LEN];
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut salt);
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let params = ScryptParams::new(self.log_n, SCRYPT_R, SCRYPT_P);
+        let key = self.key(&salt, &params);
+
+        let mut data = vec![0u8; plain.len()];
+        let mut tag = [0u8; TAG_LEN];
+        ChaCha20Poly1305::new(&key, &nonce, AAD).encrypt(plain, &mut data, &mut tag);
+        let sealed = Sealed {
+            cipher: CIPHER.to_owned(),
+            log_n: self.log_n,
+            r: SCRYPT_R,
+            p: SCRYPT_P,
+            salt: STANDARD.encode(salt),
+            nonce: STANDARD.encode(nonce),
+            tag: STANDARD.encode(tag),
+            data: STANDARD.encode(data),
+        };
+        serde_json::to_string_pretty(&sealed).expect("sealed cache is plain json")
+    }
+
+    /// Decrypt what [`Encryption::seal`] wrote.
+    pub fn open(&self, sealed: &str) -> Result<Vec<u8>> {
+        let damaged = |reason: &str| Error::decode(format!("encrypted cache: {}", reason), "");
+        let sealed: Sealed = serde_json::from_str(sealed).map_err(|e| damaged(&e.to_string()))?;
+        if sealed.cipher != CIPHER {
+            return Err(damaged(&format!("unknown cipher {}", sealed.cipher)));
+        }
+        // ScryptParams::new panics on what it does not accept
+        let valid = (1..=20).contains(&sealed.log_n)
+            && (1..=32).contains(&sealed.r)
+            && (1..=16).contains(&sealed.p)
+            && u32::from(sealed.log_n) < sealed.r * 16;
+        if !valid {
+            return Err(damaged("bad scrypt parameters"));
+        }
+        let decode = |s: &str| STANDARD.decode(s).map_err(|e| damaged(&e.to_string()));
+        let (salt, nonce, tag, data) = (
+            decode(&sealed.salt)?,
+            decode(&sealed.nonce)?,
+            decode(&sealed.tag)?,
+            decode(&sealed.data)?,
+        );
+        if nonce.len() != NONCE_LEN || tag.len() != TAG_LEN {
+            return Err(damaged("bad nonce or tag"));
+        }
+
+        let params = ScryptParams::new(sealed.log_n, sealed.r, sealed.p);
+        let key = self.key(&salt, &params);
+        let mut plain = vec![0u8; data.len()];
+        if !ChaCha20Poly1305::new(&key, &nonce, AAD).decrypt(&data, &mut plain, &tag) {
+            return Err(Error::Decrypt);
+        }
+        Ok(plain)
+    }
+
+    fn key(&self, salt: &[u8], params: &ScryptParams) -> [u8; 32] {
+        let mut key = [0u8; 32];
+        scrypt(self.passphrase.as_bytes(), salt, params, &mut key);
+        key
+    }
+}
+
+/// Whether `s` is a cache written by [`Encryption::seal`].
+pub fn is_sealed(s: &str) -> bool {
+    serde_json::from_str::<serde_json::Value>(s)
+        .ok()
+        .and_then(|v| {
+            v.get("cipher")
+                .and_then(|c| c.as_str().map(|c| c == CIPHER))
+        })
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seal_and_open_test() {
+        let enc = Encryption::new("correct horse").cost(4);
+        let sealed = enc.seal(b"{\"cookies\":\"UID=1\"}");
+        assert!(is_sealed(&sealed));
+        assert!(!is_sealed("{\"version\":2}"));
+        assert!(!sealed.contains("UID"));
+        assert_eq!(enc.open(&sealed).unwrap(), b"{\"cookies\":\"UID=1\"}");
+        // a new salt and nonce every time
+        assert_ne!(enc.seal(b"same"), enc.seal(b"same"));
+
+        assert!(matches!(
+            Encryption::new("wrong").open(&sealed),
+            Err(Error::Decrypt)
+        ));
+        let mut tampered: serde_json::Value = serde_json::from_str(&sealed).unwrap();
+        tampered["data"] = STANDARD.encode(b"{\"cookies\":\"UID=2\"}").into();
+        assert!(matches!(
+            enc.open(&tampered.to_string()),
+            Err(Error::Decrypt)
+        ));
+        tampered["log_n"] = 99.into();
+        assert!(matches!(
+            enc.open(&tampered.to_string()),
+            Err(Error::Decode { .. })
+        ));
+    }
+}
@@ -0,0 +1,218 @@
+//! Base hosts of the 115 services used by `Session`.
+//!
+//! Every url is built from one of these bases so a `Session` can be pointed
+//! at a local stand-in for 115 (integration tests, staging checks).
+use serde::{Deserialize, Serialize};
+
+const PROAPI_BASE: &str = "https://proapi.115.com";
+const WEBAPI_BASE: &str = "https://webapi.115.com";
+const UPLB_BASE: &str = "https://uplb.115.com";
+const MY_BASE: &str = "https://my.115.com";
+const QRCODE_BASE: &str = "https://qrcodeapi.115.com";
+const PASSPORT_BASE: &str = "https://passportapi.115.com";
+
+pub const PROAPI_ENV: &str = "RS115_PROAPI_URL";
+pub const WEBAPI_ENV: &str = "RS115_WEBAPI_URL";
+pub const UPLB_ENV: &str = "RS115_UPLB_URL";
+pub const MY_ENV: &str = "RS115_MY_URL";
+pub const QRCODE_ENV: &str = "RS115_QRCODE_URL";
+pub const PASSPORT_ENV: &str = "RS115_PASSPORT_URL";
+
+const INFO_PATH: &str = "/app/uploadinfo";
+const CREATE_DIR_PATH: &str = "/files/add";
+const DELETE_PATH: &str = "/rb/delete";
+const FOLDER_ID_PATH: &str = "/files/getid";
+const LIST_PATH: &str = "/files";
+const INIT_UPLOAD_PATH: &str = "/3.0/initupload.php";
+const SPACE_INFO_PATH: &str = "/files/index_info";
+const NAV_PATH: &str = "/?ct=ajax&ac=nav";
+const QR_TOKEN_PATH: &str = "/api/1.0/web/1.0/token/";
+const QR_STATUS_PATH: &str = "/get/status/";
+const QR_LOGIN_PATH: &str = "/app/1.0/web/1.0/login/qrcode/";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Endpoints {
+    pub proapi: String,
+    pub webapi: String,
+    pub uplb: String,
+    /// account pages: user name and vip state
+    pub my: String,
+    /// qr code login tokens and scan status
+    pub qrcode: String,
+    /// turns a confirmed qr code into cookies
+    pub passport: String,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self {
+            proapi: PROAPI_BASE.to_owned(),
+            webapi: WEBAPI_BASE.to_owned(),
+            uplb: UPLB_BASE.to_owned(),
+            my: MY_BASE.to_owned(),
+            qrcode: QRCODE_BASE.to_owned(),
+            passport: PASSPORT_BASE.to_owned(),
+        }
+    }
+}
+
+impl Endpoints {
+    /// Use the same base for every service, handy for a single mock server.
+    pub fn all(base: &str) -> Self {
+        Self {
+            proapi: base.to_owned(),
+            webapi: base.to_owned(),
+            uplb: base.to_owned(),
+            my: base.to_owned(),
+            qrcode: base.to_owned(),
+            passport: base.to_owned(),
+        }
+    }
+
+    /// Defaults overridden by `RS115_PROAPI_URL`, `RS115_WEBAPI_URL`,
+    /// `RS115_UPLB_URL`, `RS115_MY_URL`, `RS115_QRCODE_URL` and
+    /// `RS115_PASSPORT_URL`.
+    pub fn from_env() -> Self {
+        let mut endpoints = Self::default();
+        if let Ok(v) = std::env::var(PROAPI_ENV) {
+            endpoints.set_proapi(&v);
+        }
+        if let Ok(v) = std::env::var(WEBAPI_ENV) {
+            endpoints.set_webapi(&v);
+        }
+        if let Ok(v) = std::env::var(UPLB_ENV) {
+            endpoints.set_uplb(&v);
+        }
+        if let Ok(v) = std::env::var(MY_ENV) {
+            endpoints.set_my(&v);
+        }
+        if let Ok(v) = std::env::var(QRCODE_ENV) {
+            endpoints.set_qrcode(&v);
+        }
+        if let Ok(v) = std::env::var(PASSPORT_ENV) {
+            endpoints.set_passport(&v);
+        }
+        endpoints
+    }
+
+    pub fn set_proapi(&mut self, base: &str) {
+        self.proapi = trim_base(base);
+    }
+
+    pub fn set_webapi(&mut self, base: &str) {
+        self.webapi = trim_base(base);
+    }
+
+    pub fn set_uplb(&mut self, base: &str) {
+        self.uplb = trim_base(base);
+    }
+
+    pub fn set_my(&mut self, base: &str) {
+        self.my = trim_base(base);
+    }
+
+    pub fn set_qrcode(&mut self, base: &str) {
+        self.qrcode = trim_base(base);
+    }
+
+    pub fn set_passport(&mut self, base: &str) {
+        self.passport = trim_base(base);
+    }
+
+    pub fn info_url(&self) -> String {
+        format!("{}{}", trim_base(&self.proapi), INFO_PATH)
+    }
+
+    pub fn create_dir_url(&self) -> String {
+        format!("{}{}", trim_base(&self.webapi), CREATE_DIR_PATH)
+    }
+
+    pub fn delete_url(&self) -> String {
+        format!("{}{}", trim_base(&self.webapi), DELETE_PATH)
+    }
+
+    pub fn folder_id_url(&self) -> String {
+        format!("{}{}", trim_base(&self.webapi), FOLDER_ID_PATH)
+    }
+
+    pub fn list_url(&self) -> String {
+        format!("{}{}", trim_base(&self.webapi), LIST_PATH)
+    }
+
+    pub fn init_upload_url(&self) -> String {
+        format!("{}{}", trim_base(&self.uplb), INIT_UPLOAD_PATH)
+    }
+
+    pub fn space_info_url(&self) -> String {
+        format!("{}{}", trim_base(&self.webapi), SPACE_INFO_PATH)
+    }
+
+    pub fn nav_url(&self) -> String {
+        format!("{}{}", trim_base(&self.my), NAV_PATH)
+    }
+
+    pub fn qr_token_url(&self) -> String {
+        format!("{}{}", trim_base(&self.qrcode), QR_TOKEN_PATH)
+    }
+
+    pub fn qr_status_url(&self) -> String {
+        format!("{}{}", trim_base(&self.qrcode), QR_STATUS_PATH)
+    }
+
+    pub fn qr_login_url(&self) -> String {
+        format!("{}{}", trim_base(&self.passport), QR_LOGIN_PATH)
+    }
+}
+
+fn trim_base(base: &str) -> String {
+    base.trim().trim_end_matches('/').to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_urls_test() {
+        let endpoints = Endpoints::default();
+        assert_eq!(
+            endpoints.info_url(),
+            "https://proapi.115.com/app/uploadinfo"
+        );
+        assert_eq!(
+            endpoints.create_dir_url(),
+            "https://webapi.115.com/files/add"
+        );
+        assert_eq!(endpoints.delete_url(), "https://webapi.115.com/rb/delete");
+        assert_eq!(
+            endpoints.folder_id_url(),
+            "https://webapi.115.com/files/getid"
+        );
+        assert_eq!(endpoints.list_url(), "https://webapi.115.com/files");
+        assert_eq!(
+            endpoints.init_upload_url(),
+            "https://uplb.115.com/3.0/initupload.php"
+        );
+        assert_eq!(
+            endpoints.space_info_url(),
+            "https://webapi.115.com/files/index_info"
+        );
+        assert_eq!(endpoints.nav_url(), "https://my.115.com/?ct=ajax&ac=nav");
+        assert_eq!(
+            endpoints.qr_login_url(),
+            "https://passportapi.115.com/app/1.0/web/1.0/login/qrcode/"
+        );
+    }
+
+    #[test]
+    fn override_trailing_slash_test() {
+        let mut endpoints = Endpoints::all("http://127.0.0.1:8115/");
+        assert_eq!(endpoints.info_url(), "http://127.0.0.1:8115/app/uploadinfo");
+        endpoints.set_uplb("http://localhost:9000//");
+        assert_eq!(
+            endpoints.init_upload_url(),
+            "http://localhost:9000/3.0/initupload.php"
+        );
+    }
+}
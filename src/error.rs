@@ -0,0 +1,258 @@
+//! The error type shared by every `Session` and `Runtime` method.
+use crate::api::QrStatus;
+use crate::cookies::CookieError;
+use crate::redact;
+
+/// errno returned by webapi when a folder with the same name already exists.
+pub(crate) const ERRNO_DIR_EXIST: i64 = 20004;
+/// errno returned by webapi once the login cookies are no longer valid.
+#[cfg(any(feature = "blocking", feature = "async"))]
+pub(crate) const ERRNO_LOGIN_EXPIRED: i64 = 990001;
+/// statuscode returned by `initupload.php` when the name hits the censor list.
+pub(crate) const STATUS_FORBIDDEN_NAME: i64 = 414;
+/// statuscode / errno 115 answers with when requests come too fast.
+pub(crate) const STATUS_RATE_LIMITED: i64 = 429;
+/// statuscode / errno 115 answers with until the account passes a captcha.
+pub(crate) const ERRNO_VERIFY_REQUIRED: i64 = 911;
+/// statuscode of `initupload.php` when the `sig` does not match the user key.
+#[cfg(any(feature = "blocking", feature = "async"))]
+pub(crate) const STATUS_BAD_SIG: i64 = 990009;
+/// statuscode of `initupload.php` when the app version is no longer accepted.
+pub(crate) const STATUS_OLD_CLIENT: i64 = 990012;
+/// statuscode of `initupload.php` when the target folder already holds the file.
+pub(crate) const STATUS_FILE_EXISTS: i64 = 20022;
+
+/// How much of an unexpected response body is kept in errors.
+const SNIPPET_LEN: usize = 200;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// the request never got a response: dns, connect, tls, timeout...
+    #[cfg(any(feature = "blocking", feature = "async"))]
+    Network(reqwest::Error),
+    /// a network error of a call the agent made, only its message crosses
+    /// the socket
+    RemoteNetwork(String),
+    /// the proxy or certificates of the network config can not be used
+    HttpClient(String),
+    /// 115 answered with a non 2xx http status
+    HttpStatus { status: u16, snippet: String },
+    /// the response body is not the json we expect
+    Decode { reason: String, snippet: String },
+    /// 115 answered with a non zero statuscode / errno
+    Api { code: i64, message: String },
+    /// rapid upload was answered but did not complete (status 1: not in 115)
+    RapidUpload { status: i64, message: String },
+    /// 115 wants a human to pass a captcha before taking more requests, code 911
+    VerificationRequired { message: String },
+    /// name not allowed by 115, statuscode 414
+    ForbiddenName,
+    /// the folder to create already exists, errno 20004
+    DirExist,
+    /// the folder uploaded to already holds the file, statuscode 20022
+    FileExists,
+    /// no folder at this path in 115
+    FolderNotFound(String),
+    /// cookies are missing, expired or rejected by 115
+    AuthExpired,
+    /// cookies are set but `user_id`/`user_key` were never fetched
+    MissingUserKey,
+    /// 115 rejected the upload signature or its login: the `user_key` is stale
+    StaleUserKey { code: i64, message: String },
+    /// 115 no longer accepts the app version of the client rs115 presents as
+    ClientRetired {
+        app_version: String,
+        message: String,
+    },
+    /// no cookies set, run `set-cookies` first
+    NotLoggedIn,
+    /// the cookies given to `set-cookies` can not be used
+    Cookies(CookieError),
+    /// the qr code login ended without a login: expired or canceled
+    QrLogin(QrStatus),
+    /// no cached profile with this name
+    UnknownProfile(String),
+    /// profile names are limited to `[A-Za-z0-9_.-]`
+    InvalidProfileName(String),
+    /// the cookie cache is encrypted and no passphrase was given
+    PassphraseRequired,
+    /// wrong passphrase, or the encrypted cookie cache was modified
+    Decrypt,
+    /// the agent refused or failed a request, or can not be reached
+    Agent(String),
+    /// local file system errors
+    Io(std::io::Error),
+}
+
+impl Error {
+    /// Build a `Decode` error keeping a short, char boundary safe prefix of `body`.
+    pub(crate) fn decode(reason: impl ToString, body: &str) -> Self {
+        Error::Decode {
+            reason: reason.to_string(),
+            snippet: snippet(body),
+        }
+    }
+
+    /// The 115 statuscode / errno, or the http status, the error came with.
+    pub fn code(&self) -> Option<i64> {
+        match self {
+            Error::HttpStatus { status, .. } => Some((*status).into()),
+            Error::Api { code, .. } | Error::StaleUserKey { code, .. } => Some(*code),
+            Error::RapidUpload { status, .. } => Some(*status),
+            Error::VerificationRequired { .. } => Some(ERRNO_VERIFY_REQUIRED),
+            Error::ForbiddenName => Some(STATUS_FORBIDDEN_NAME),
+            Error::DirExist => Some(ERRNO_DIR_EXIST),
+            Error::FileExists => Some(STATUS_FILE_EXISTS),
+            Error::ClientRetired { .. } => Some(STATUS_OLD_CLIENT),
+            _ => None,
+        }
+    }
+
+    #[cfg(any(feature = "blocking", feature = "async", test))]
+    pub(crate) fn http_status(status: u16, body: &str) -> Self {
+        Error::HttpStatus {
+            status,
+            snippet: snippet(body),
+        }
+    }
+}
+
+/// A short prefix of `body`, cut on a char boundary, with the user keys and
+/// ids it may hold masked.
+pub(crate) fn snippet(body: &str) -> String {
+    let body = redact::json(body);
+    match body.char_indices().nth(SNIPPET_LEN) {
+        Some((i, _)) => format!("{}...", &body[..i]),
+        None => body,
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            #[cfg(any(feature = "blocking", feature = "async"))]
+            Error::Network(e) => write!(f, "network request error: {}", e),
+            Error::RemoteNetwork(message) => write!(f, "network request error: {}", message),
+            Error::HttpClient(message) => write!(f, "http client setup failed: {}", message),
+            Error::HttpStatus { status, snippet } => {
+                write!(f, "http status {}: {}", status, snippet)
+            }
+            Error::Decode { reason, snippet } => {
+                write!(f, "unexpected response ({}): {}", reason, snippet)
+            }
+            Error::Api { code, message } => write!(f, "115 error {}: {}", code, message),
+            Error::RapidUpload { status, message } => {
+                write!(f, "rapid upload not done, status {}: {}", status, message)
+            }
+            Error::VerificationRequired { message } => write!(
+                f,
+                "115 asks for a human verification ({}), open 115.com in a browser and pass it",
+                message
+            ),
+            Error::ForbiddenName => write!(
+                f,
+                "name not allowed by 115, filename may contains word in the censor list"
+            ),
+            Error::DirExist => write!(f, "create folder failed, dir already exist"),
+            Error::FileExists => write!(f, "the file already exists in the folder"),
+            Error::FolderNotFound(path) => write!(f, "no folder {} in 115", path),
+            Error::AuthExpired => write!(f, "login expired, please set cookies again"),
+            Error::MissingUserKey => write!(f, "missing userid or userkey"),
+            Error::StaleUserKey { code, message } => write!(
+                f,
+                "upload signature rejected, the user key is stale ({}: {})",
+                code, message
+            ),
+            Error::ClientRetired {
+                app_version,
+                message,
+            } => write!(
+                f,
+                "115 no longer accepts client version {} ({}), use a newer client preset such as {}",
+                app_version,
+                message,
+                crate::client::ClientIdentity::newest_preset()
+            ),
+            Error::NotLoggedIn => write!(f, "cookies not set"),
+            Error::Cookies(e) => write!(f, "invalid cookies: {}", e),
+            Error::QrLogin(status) => write!(f, "qr code login failed, {}", status),
+            Error::UnknownProfile(name) => write!(f, "no profile named {}", name),
+            Error::InvalidProfileName(name) => write!(
+                f,
+                "invalid profile name \"{}\", use letters, digits, '_', '.' and '-'",
+                name
+            ),
+            Error::PassphraseRequired => {
+                write!(f, "the cookie cache is encrypted, a passphrase is required")
+            }
+            Error::Decrypt => write!(
+                f,
+                "can not decrypt the cookie cache, wrong passphrase or damaged file"
+            ),
+            Error::Agent(message) => write!(f, "agent: {}", message),
+            Error::Io(e) => write!(f, "io error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            #[cfg(any(feature = "blocking", feature = "async"))]
+            Error::Network(e) => Some(e),
+            Error::Io(e) => Some(e),
+            Error::Cookies(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(any(feature = "blocking", feature = "async"))]
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Network(e)
+    }
+}
+
+impl From<CookieError> for Error {
+    fn from(e: CookieError) -> Self {
+        Error::Cookies(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::decode(e, "")
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snippet_is_truncated_on_char_boundary_test() {
+        let body = "名".repeat(SNIPPET_LEN + 10);
+        match Error::decode("bad json", &body) {
+            Error::Decode { snippet, .. } => {
+                assert_eq!(snippet, format!("{}...", "名".repeat(SNIPPET_LEN)))
+            }
+            e => panic!("unexpected {:?}", e),
+        }
+        match Error::http_status(502, "bad gateway") {
+            Error::HttpStatus { status, snippet } => {
+                assert_eq!(status, 502);
+                assert_eq!(snippet, "bad gateway");
+            }
+            e => panic!("unexpected {:?}", e),
+        }
+    }
+}
@@ -0,0 +1,276 @@
+//! [`Sha1JsonFolderEntity`] trees of local directories, what `import-tree`
+//! recreates in 115.
+//!
+//! The directory is walked first, filtered by the include and exclude globs,
+//! then every file left is hashed into its `115://` link by [`hash_files`],
+//! several at once.
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, Result};
+use crate::hash::{hash_files, HashConfig, Progress};
+use crate::parsers::{Sha1JsonFolderEntity, Sha1Link};
+
+/// What to do with symbolic links met in the walk.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Symlinks {
+    /// leave them out
+    #[default]
+    Skip,
+    /// export the files they point to, leave out links to directories
+    Files,
+    /// export what they point to, directories included; a directory linking
+    /// back to one it is in is left out
+    Follow,
+}
+
+impl std::str::FromStr for Symlinks {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(Symlinks::Skip),
+            "files" => Ok(Symlinks::Files),
+            "follow" => Ok(Symlinks::Follow),
+            _ => Err(format!(
+                "unknown symlink mode {}, use skip, files or follow",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ExportOptions {
+    /// globs of the files to export, every file when empty
+    pub include: Vec<String>,
+    /// globs of the files and directories to leave out
+    pub exclude: Vec<String>,
+    pub symlinks: Symlinks,
+    pub hashing: HashConfig,
+}
+
+/// An exported tree and what could not be part of it.
+#[derive(Debug)]
+pub struct Export {
+    pub tree: Sha1JsonFolderEntity,
+    /// files hashed into the tree
+    pub files: usize,
+    /// bytes of these files
+    pub size: u64,
+    /// files that could not be hashed, and directories that could not be read
+    pub failed: Vec<(PathBuf, Error)>,
+}
+
+/// A directory found by the walk, not hashed yet.
+struct Dir {
+    name: String,
+    /// indexes in `Walk::files`
+    files: Vec<usize>,
+    dirs: Vec<Dir>,
+}
+
+struct Walk<'a> {
+    options: &'a ExportOptions,
+    include: GlobSet,
+    exclude: GlobSet,
+    /// every file to hash, in the order of the tree
+    files: Vec<PathBuf>,
+    failed: Vec<(PathBuf, Error)>,
+}
+
+/// The tree of `dir`: its files as links, its sub directories as trees.
+/// Directories left without files by the filters are left out.
+pub fn export_tree(dir: impl AsRef<Path>, options: &ExportOptions) -> Result<Export> {
+    export_tree_with_progress(dir, options, |_| {})
+}
+
+/// [`export_tree`], telling `progress` how far the hashing is.
+pub fn export_tree_with_progress(
+    dir: impl AsRef<Path>,
+    options: &ExportOptions,
+    progress: impl FnMut(&Progress),
+) -> Result<Export> {
+    let dir = dir.as_ref();
+    let root = fs::canonicalize(dir)?;
+    if !root.is_dir() {
+        return Err(invalid(format!("{} is not a directory", dir.display())));
+    }
+    let name = root
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut walk = Walk {
+        options,
+        include: glob_set(&options.include)?,
+        exclude: glob_set(&options.exclude)?,
+        files: Vec::new(),
+        failed: Vec::new(),
+    };
+    let mut ancestors = HashSet::from([root.clone()]);
+    let found = walk.dir(name, dir, Path::new(""), &mut ancestors)?;
+
+    let mut links: Vec<_> = hash_files(&walk.files, &options.hashing, progress)
+        .into_iter()
+        .map(Some)
+        .collect();
+    let mut export = Export {
+        tree: Sha1JsonFolderEntity::default(),
+        files: 0,
+        size: 0,
+        failed: walk.failed,
+    };
+    export.tree = tree(found, &walk.files, &mut links, &mut export);
+    Ok(export)
+}
+
+impl Walk<'_> {
+    /// The files and directories under `path`, `rel` from the exported one.
+    fn dir(
+        &mut self,
+        name: String,
+        path: &Path,
+        rel: &Path,
+        ancestors: &mut HashSet<PathBuf>,
+    ) -> Result<Dir> {
+        let mut entries = fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        entries.sort();
+
+        let mut found = Dir {
+            name,
+            files: Vec::new(),
+            dirs: Vec::new(),
+        };
+        for entry in entries {
+            let file_name = entry.file_name().unwrap_or_default().to_owned();
+            let rel = rel.join(&file_name);
+            if self.exclude.is_match(&rel) {
+                continue;
+            }
+            let link = match fs::symlink_metadata(&entry) {
+                Ok(metadata) => metadata.file_type().is_symlink(),
+                Err(e) => {
+                    self.failed.push((entry, e.into()));
+                    continue;
+                }
+            };
+            if link && self.options.symlinks == Symlinks::Skip {
+                continue;
+            }
+            // follows links, a broken one counts as failed
+            let metadata = match fs::metadata(&entry) {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    self.failed.push((entry, e.into()));
+                    continue;
+                }
+            };
+            if metadata.is_dir() {
+                if link && self.options.symlinks == Symlinks::Files {
+                    continue;
+                }
+                let real = match fs::canonicalize(&entry) {
+                    Ok(real) => real,
+                    Err(e) => {
+                        self.failed.push((entry, e.into()));
+                        continue;
+                    }
+                };
+                // a link back up the walk would never end
+                if !ancestors.insert(real.clone()) {
+                    continue;
+                }
+                let name = file_name.to_string_lossy().into_owned();
+                match self.dir(name, &entry, &rel, ancestors) {
+                    Ok(dir) if !dir.files.is_empty() || !dir.dirs.is_empty() => {
+                        found.dirs.push(dir)
+                    }
+                    Ok(_) => {}
+                    Err(e) => self.failed.push((entry, e)),
+                }
+                ancestors.remove(&real);
+            } else if metadata.is_file() && (self.include.is_empty() || self.include.is_match(&rel))
+            {
+                found.files.push(self.files.len());
+                self.files.push(entry);
+            }
+        }
+        Ok(found)
+    }
+}
+
+/// The tree of a walked `dir`, with the `links` hashed from `paths`.
+fn tree(
+    dir: Dir,
+    paths: &[PathBuf],
+    links: &mut [Option<Result<Sha1Link>>],
+    export: &mut Export,
+) -> Sha1JsonFolderEntity {
+    let mut files = Vec::with_capacity(dir.files.len());
+    for i in dir.files {
+        match links[i].take().expect("every file is in one directory") {
+            Ok(link) => {
+                export.files += 1;
+                export.size += link.size;
+                files.push(link.to_string());
+            }
+            Err(e) => export.failed.push((paths[i].clone(), e)),
+        }
+    }
+    let dirs = dir
+        .dirs
+        .into_iter()
+        .map(|dir| tree(dir, paths, links, export))
+        .collect();
+    Sha1JsonFolderEntity {
+        dir_name: dir.name,
+        files,
+        dirs,
+    }
+}
+
+/// `globs` matched against paths relative to the exported directory; a glob
+/// without `/` matches names at any depth, like in `.gitignore`.
+fn glob_set(globs: &[String]) -> Result<GlobSet> {
+    let mut set = GlobSetBuilder::new();
+    for glob in globs {
+        let pattern = match glob.trim_start_matches('/') {
+            pattern if glob.contains('/') => pattern.to_owned(),
+            pattern => format!("**/{}", pattern),
+        };
+        let glob = GlobBuilder::new(&pattern)
+            .literal_separator(true)
+            .build()
+            .map_err(|e| invalid(format!("invalid glob {}: {}", glob, e)))?;
+        set.add(glob);
+    }
+    set.build()
+        .map_err(|e| invalid(format!("invalid globs: {}", e)))
+}
+
+fn invalid(message: String) -> Error {
+    Error::Io(std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        message,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_test() {
+        let set = glob_set(&["*.mkv".to_owned(), "/extras/*".to_owned()]).unwrap();
+        assert!(set.is_match("a.mkv"));
+        assert!(set.is_match("season 1/e01.mkv"));
+        assert!(set.is_match("extras/making of.mp4"));
+        assert!(!set.is_match("season 1/extras/making of.mp4"));
+        assert!(!set.is_match("a.mkv.part"));
+        assert!(glob_set(&["[".to_owned()]).is_err());
+    }
+}
@@ -1,188 +1,900 @@
+#[cfg(all(unix, feature = "agent"))]
+use crate::agent::AgentClient;
+use crate::client::ClientIdentity;
+use crate::cookies::CookieJar;
 use crate::dummies::get_a_hash;
+use crate::endpoints::Endpoints;
+use crate::error::{Error, Result};
+use crate::parsers::{Sha1JsonFolderEntity, Sha1Link};
+use crate::profiles::{check_profile_name, ProfileCache};
+use crate::qrlogin::QrLogin;
+use crate::ratelimit::{ApiCall, RateLimit, RateLimitConfig, RateLimiter};
+use crate::store::{default_store, SessionStore};
 use crate::upload::*;
-use std::fs::remove_file;
-use std::fs::File;
 use std::io::BufRead;
+use std::io::IsTerminal;
 use std::io::Write;
 
-const COOKIES_FILE: &str = ".COOKIES_115.cache";
-
+/// The cli state: the cached login profiles, the session of the one in use
+/// plus how to reach 115.
 #[derive(Debug)]
 pub struct Runtime {
+    store: Box<dyn SessionStore>,
+    cache: ProfileCache,
+    /// profile named on the command line, `None` for the cache default
+    profile: Option<String>,
     session: Option<Session>,
+    config: SessionConfig,
+    /// wait for the user to pass 115's captcha instead of stopping bulk jobs
+    pause_on_verification: bool,
+    /// makes the calls instead of `session` when set
+    #[cfg(all(unix, feature = "agent"))]
+    agent: Option<AgentClient>,
 }
 impl Runtime {
-    pub fn new() -> Self {
-        let path = std::env::current_exe();
-
-        if let Ok(mut path) = path {
-            path.pop();
-            path.push(COOKIES_FILE);
-
-            if path.exists() {
-                let f = File::open(path).expect("should be able to open file");
-                if let Ok(session) = serde_json::from_reader(f) {
-                    let session = Some(session);
-                    return Self { session };
-                }
-            }
-        }
-        Self { session: None }
+    pub fn new() -> Result<Self> {
+        Self::with_endpoints(Endpoints::from_env())
     }
 
-    pub fn has_cookies(&self) -> bool {
-        self.session.is_some()
+    pub fn with_endpoints(endpoints: Endpoints) -> Result<Self> {
+        Self::with_config(SessionConfig {
+            endpoints,
+            ..Default::default()
+        })
     }
 
-    pub fn print_cookies(&self) -> String {
-        self.session.as_ref().unwrap().cookies.to_owned()
+    pub fn with_config(config: SessionConfig) -> Result<Self> {
+        Self::with_profile(config, None)
+    }
+
+    /// Work on the cached profile `profile`, the cache default when `None`,
+    /// of the [`default_store`], failing if it can not be read.
+    pub fn with_profile(config: SessionConfig, profile: Option<&str>) -> Result<Self> {
+        Self::with_store(config, profile, default_store())
     }
 
-    pub fn set_cookies(&mut self, cookies: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let new_sesion = Session::new(cookies.to_owned());
-        self.session = Some(new_sesion);
+    /// Work on the profile `profile` of `store`, failing if it can not be
+    /// read or if the http client of its session can not be set up, e.g. for
+    /// an invalid proxy.
+    pub fn with_store(
+        config: SessionConfig,
+        profile: Option<&str>,
+        store: Box<dyn SessionStore>,
+    ) -> Result<Self> {
+        let cache = store.load()?;
+        Self::from_parts(store, cache, config, profile)
+    }
 
-        let mut path = std::env::current_exe()?;
-        path.pop();
-        path.push(COOKIES_FILE);
+    /// Work on the profile `profile` of a running agent, which makes every
+    /// call: the cookies stay in the agent.
+    #[cfg(all(unix, feature = "agent"))]
+    pub fn with_agent(
+        config: SessionConfig,
+        profile: Option<&str>,
+        agent: AgentClient,
+    ) -> Result<Self> {
+        let cache = agent.profiles()?;
+        Ok(Self {
+            store: Box::new(agent.clone()),
+            cache,
+            profile: profile.map(str::to_owned),
+            session: None,
+            config,
+            pause_on_verification: std::io::stdin().is_terminal(),
+            agent: Some(agent),
+        })
+    }
+
+    fn from_parts(
+        store: Box<dyn SessionStore>,
+        cache: ProfileCache,
+        config: SessionConfig,
+        profile: Option<&str>,
+    ) -> Result<Self> {
+        let mut rt = Self {
+            store,
+            cache,
+            profile: profile.map(str::to_owned),
+            session: None,
+            config,
+            pause_on_verification: std::io::stdin().is_terminal(),
+            #[cfg(all(unix, feature = "agent"))]
+            agent: None,
+        };
+        rt.session = rt.load_session()?;
+        Ok(rt)
+    }
 
-        if path.exists() {
-            remove_file(&path)?
+    /// The session of the profile in use, `None` if it has no cookies.
+    fn load_session(&self) -> Result<Option<Session>> {
+        match self.cache.get(self.profile_name()) {
+            Some(profile) => profile.builder_with(self.config.clone()).build().map(Some),
+            None => Ok(None),
         }
+    }
 
-        let f = File::create(path)?;
-        let mut new_session = Session::new(cookies.to_owned());
-        new_session.get_key_if_none()?;
+    /// What makes the check and import calls: the agent if any, else the
+    /// session with its user key fetched if the store had none.
+    fn calls(&mut self) -> Result<Box<dyn Calls + '_>> {
+        #[cfg(all(unix, feature = "agent"))]
+        if let Some(ref agent) = self.agent {
+            return Ok(Box::new(AgentProfile {
+                agent,
+                profile: self.cache.resolve(self.profile.as_deref()),
+            }));
+        }
+        let session = self.session.as_mut().ok_or(Error::NotLoggedIn)?;
+        session.get_key_if_none()?;
+        Ok(Box::new(&*session))
+    }
 
-        serde_json::to_writer(f, &new_session)?;
+    /// Name of the profile this runtime works on.
+    pub fn profile_name(&self) -> &str {
+        self.cache.resolve(self.profile.as_deref())
+    }
 
-        Ok(())
+    /// Every cached profile and which one is the default.
+    pub fn profiles(&self) -> &ProfileCache {
+        &self.cache
     }
 
-    pub fn clean(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let path = std::env::current_exe();
-        if let Ok(mut path) = path {
-            path.pop();
-            path.push(COOKIES_FILE);
+    /// Make `name` the profile used when none is named.
+    pub fn use_profile(&mut self, name: &str) -> Result<()> {
+        self.cache.set_default(name)?;
+        self.store.save(&self.cache)
+    }
 
-            if path.exists() {
-                remove_file(path)?;
-            }
-        }
+    pub fn remove_profile(&mut self, name: &str) -> Result<()> {
+        self.cache.remove(name)?;
+        self.store.save(&self.cache)?;
+        self.session = self.load_session()?;
         Ok(())
     }
 
-    pub fn check_name(&self, name: &str) -> Result<bool, Box<dyn std::error::Error>> {
-        let session = match self.session {
-            Some(ref s) => s,
-            None => return Err("cookies not set".into()),
-        };
+    /// The client the profile in use presents itself as: the one of the
+    /// config, else the profile's, else the default one.
+    pub fn client(&self) -> ClientIdentity {
+        self.config
+            .client
+            .clone()
+            .or_else(|| self.cache.get(self.profile_name())?.client.clone())
+            .unwrap_or_default()
+    }
 
-        let folder_id = session
-            .create_folder(0, "TMP_rs115".into())
-            .expect("can create folder");
+    /// Make the profile in use present itself as `client`, the default
+    /// client when `None`.
+    pub fn set_client(&mut self, client: Option<ClientIdentity>) -> Result<()> {
+        let name = self.profile_name().to_owned();
+        let mut profile = self.cache.get(&name).cloned().ok_or(Error::NotLoggedIn)?;
+        profile.client = client;
+        self.cache.insert(&name, profile)?;
+        self.store.save(&self.cache)?;
+        self.session = self.load_session()?;
+        Ok(())
+    }
 
-        let hash = get_a_hash();
+    /// The agent making the calls, if the runtime was made by
+    /// [`Runtime::with_agent`].
+    #[cfg(all(unix, feature = "agent"))]
+    pub fn agent(&self) -> Option<&AgentClient> {
+        self.agent.as_ref()
+    }
 
-        let is_uploaded = match session.upload115_sha1(
-            name.to_owned(),
-            "5".to_owned(),
-            hash.to_owned(),
-            hash,
-            folder_id,
-        ) {
-            Ok(_) => true,
-            Err(e) => {
-                if e.is::<FileNameForbiddenError>() {
-                    if session.delete_one(0, folder_id).is_err() {
-                        eprintln!("fail to delete the folder TMP_rs115")
-                    }
-                    return Ok(false);
-                }
-                false
-            }
-        };
+    /// The cached session, if cookies were set.
+    pub fn session(&self) -> Option<&Session> {
+        self.session.as_ref()
+    }
+
+    /// Whether bulk checks wait on stdin when 115 asks for a human
+    /// verification, or stop. Defaults to waiting when stdin is a terminal.
+    pub fn set_pause_on_verification(&mut self, pause: bool) {
+        self.pause_on_verification = pause;
+    }
 
-        if session.delete_one(0, folder_id).is_err() {
-            eprintln!("fail to delete the folder TMP_rs115")
+    /// Check the cached session against 115, see [`Session::account_info`].
+    pub fn account_info(&self) -> Result<AccountInfo> {
+        #[cfg(all(unix, feature = "agent"))]
+        if let Some(ref agent) = self.agent {
+            return agent.account_info(Some(self.profile_name()));
+        }
+        match self.session {
+            Some(ref session) => session.account_info(),
+            None => Err(Error::NotLoggedIn),
         }
+    }
 
-        if is_uploaded {
-            Ok(true)
-        } else {
-            Err(UnknownError().into())
+    /// Start a qr code login with this runtime's endpoints and client settings;
+    /// pass the cookies it yields to [`Runtime::set_cookies`].
+    pub fn qr_login(&self) -> Result<QrLogin> {
+        QrLogin::start(&self.config)
+    }
+
+    pub fn has_cookies(&self) -> bool {
+        self.session.is_some()
+    }
+
+    pub fn print_cookies(&self) -> String {
+        self.session.as_ref().unwrap().cookies.to_owned()
+    }
+
+    /// Check and normalise `cookies`, log in with them and cache the session
+    /// as the profile in use. Returns warnings about what was fixed up in
+    /// the pasted cookies.
+    pub fn set_cookies(&mut self, cookies: &str) -> Result<Vec<String>> {
+        let name = self.profile_name().to_owned();
+        check_profile_name(&name)?;
+        let (jar, warnings) = CookieJar::parse(cookies)?;
+        // logging in again keeps the client chosen for the profile
+        let mut config = self.config.clone();
+        if config.client.is_none() {
+            config.client = self.cache.get(&name).and_then(|p| p.client.clone());
         }
+        let mut new_session = Session::builder(jar.to_string()).config(config).build()?;
+        new_session.get_key_if_none()?;
+
+        self.cache.insert(&name, new_session.profile())?;
+        self.store.save(&self.cache)?;
+        self.session = Some(new_session);
+
+        Ok(warnings)
+    }
+
+    /// Remove the cookie cache, every profile included.
+    pub fn clean(&mut self) -> Result<()> {
+        self.store.clear()?;
+        self.cache = ProfileCache::default();
+        self.session = None;
+        Ok(())
+    }
+
+    pub fn check_name(&mut self, name: &str) -> Result<bool> {
+        let session = self.calls()?;
+        let res = check_one(&*session, name);
+        drop(session);
+        self.save_refreshed_key();
+        res
     }
 
     pub fn check_name_bulk_to_file<T: BufRead, U: Write>(
-        &self,
+        &mut self,
         file: T,
-        mut forbiden_list: Option<U>,
-        mut check_fail: Option<U>,
+        forbiden_list: Option<U>,
+        check_fail: Option<U>,
         interval: Option<u64>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let mut sleep_time = std::time::Duration::from_millis(1000);
+    ) -> Result<()> {
+        let pause_on_verification = self.pause_on_verification;
+        let session = self.calls()?;
+        let res = check_bulk(
+            &*session,
+            file,
+            forbiden_list,
+            check_fail,
+            interval,
+            pause_on_verification,
+        );
+        drop(session);
+        self.save_refreshed_key();
+        res
+    }
 
-        if let Some(t) = interval {
-            sleep_time = std::time::Duration::from_millis(t)
-        }
+    /// Rapid-upload the `115://` links of `links`, one per line, into the
+    /// folder `to`: a cid, or a path like `/movies/2024`. Links that were not
+    /// imported are written to `failed`.
+    pub fn import<T: BufRead, U: Write>(
+        &mut self,
+        links: T,
+        to: &str,
+        failed: Option<U>,
+    ) -> Result<ImportSummary> {
+        let pause_on_verification = self.pause_on_verification;
+        let session = self.calls()?;
+        let res = import_bulk(&*session, links, to, failed, pause_on_verification);
+        drop(session);
+        self.save_refreshed_key();
+        res
+    }
 
-        let session = match self.session {
-            Some(ref s) => s,
-            None => return Err("cookies not set".into()),
+    /// Recreate the folders of `tree` in the folder `to`, a cid or a path,
+    /// and rapid-upload their `115://` links into them. Folders that already
+    /// exist are reused, so importing a tree again resumes it.
+    pub fn import_tree(
+        &mut self,
+        tree: &Sha1JsonFolderEntity,
+        to: &str,
+    ) -> Result<TreeImportSummary> {
+        let mut import = TreeImport {
+            pause_on_verification: self.pause_on_verification,
+            summary: TreeImportSummary::default(),
         };
+        let session = self.calls()?;
+        let res = import.run(&*session, tree, to);
+        drop(session);
+        self.save_refreshed_key();
+        res.map(|()| import.summary)
+    }
+
+    /// Keep the user key the session fetched again after 115 rejected the
+    /// cached one.
+    fn save_refreshed_key(&mut self) {
+        let profile = match self.session {
+            Some(ref session) if session.key_refreshes() > 0 => session.profile(),
+            _ => return,
+        };
+        let name = self.profile_name().to_owned();
+        if self.cache.get(&name) == Some(&profile) {
+            return;
+        }
+        let saved = self
+            .cache
+            .insert(&name, profile)
+            .and_then(|_| self.store.save(&self.cache));
+        if let Err(e) = saved {
+            eprintln!("warning: the refreshed user key was not saved, {}", e);
+        }
+    }
+}
 
-        let folder_id = session.create_folder(0, "TMP_rs115".into())?;
-
-        for line in file.lines() {
-            let line = line?;
-            let hash = get_a_hash();
-            match session.upload115_sha1(
-                line.to_owned(),
-                "5".to_owned(),
-                hash.to_owned(),
-                hash,
-                folder_id,
-            ) {
+fn check_one(session: &dyn Calls, name: &str) -> Result<bool> {
+    let folder_id = session.create_folder(0, "TMP_rs115".into())?;
+
+    let res = session.rapid_upload(&probe(name, folder_id));
+
+    if session.delete_one(0, folder_id).is_err() {
+        eprintln!("fail to delete the folder TMP_rs115")
+    }
+
+    match res {
+        Ok(_) => Ok(true),
+        Err(Error::ForbiddenName) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+fn check_bulk<T: BufRead, U: Write>(
+    session: &dyn Calls,
+    file: T,
+    mut forbiden_list: Option<U>,
+    mut check_fail: Option<U>,
+    interval: Option<u64>,
+    pause_on_verification: bool,
+) -> Result<()> {
+    // pacing on top of the session's own rate limit, only if asked for
+    let pacing = interval.map(|t| {
+        let limit = RateLimit::every(std::time::Duration::from_millis(t));
+        RateLimiter::new(&RateLimitConfig {
+            global: Some(limit),
+            ..RateLimitConfig::unlimited()
+        })
+    });
+
+    let folder_id = session.create_folder(0, "TMP_rs115".into())?;
+
+    let retries_before = session.retry_count();
+    // the temporary folder is deleted however the run ends
+    let run = || -> Result<()> {
+        let mut lines = file.lines();
+        // a line to check again after a human verification
+        let mut pending = None;
+        loop {
+            let line = match pending.take() {
+                Some(line) => line,
+                None => match lines.next() {
+                    Some(line) => line?,
+                    None => break,
+                },
+            };
+            if let Some(ref pacing) = pacing {
+                pacing.acquire(ApiCall::InitUpload);
+            }
+            let retries = session.retry_count();
+            let throttle = session.throttle_factor();
+            let res = session.rapid_upload(&probe(&line, folder_id));
+            let retried = retried(session, retries);
+            let slowed = session.throttle_factor();
+            if slowed < throttle {
+                println!(
+                    "rate limited by 115, slowing down to {:.0}% of the request rate",
+                    slowed * 100.0
+                );
+            }
+            match res {
                 Ok(_) => {
-                    println!("checked {}", line);
+                    println!("checked {}{}", line, retried);
                 }
-                Err(e) => {
-                    if e.is::<FileNameForbiddenError>() {
-                        println!("NAME NOT ALLOW: {}", line);
-                        if let Some(ref mut forbiden_list) = forbiden_list {
-                            writeln!(forbiden_list, "{}", line)?;
-                        }
-                    } else {
-                        println!("failed to check: {}, cause by: {}", line, e);
-                        if let Some(ref mut check_fail) = check_fail {
-                            writeln!(check_fail, "{}", line)?;
+                Err(Error::ForbiddenName) => {
+                    println!("NAME NOT ALLOW: {}{}", line, retried);
+                    if let Some(ref mut forbiden_list) = forbiden_list {
+                        writeln!(forbiden_list, "{}", line)?;
+                    }
+                }
+                Err(Error::VerificationRequired { message }) => {
+                    let stopped = stopped_bulk(check_fail.is_some());
+                    if wait_for_verification(&message, pause_on_verification, stopped) {
+                        pending = Some(line);
+                        continue;
+                    }
+                    // stopped: keep what is left so the job can be resumed
+                    if let Some(ref mut check_fail) = check_fail {
+                        writeln!(check_fail, "{}", line)?;
+                        for line in lines {
+                            writeln!(check_fail, "{}", line?)?;
                         }
                     }
+                    return Err(Error::VerificationRequired { message });
+                }
+                Err(e) => {
+                    println!("failed to check: {}, cause by: {}{}", line, e, retried);
+                    if let Some(ref mut check_fail) = check_fail {
+                        writeln!(check_fail, "{}", line)?;
+                    }
                 }
             };
+        }
+        Ok(())
+    };
+    let res = run();
 
-            std::thread::sleep(sleep_time);
+    if session.delete_one(0, folder_id).is_err() {
+        eprintln!("fail to delete the folder TMP_rs115");
+    }
+    res?;
+    let retries = session.retry_count() - retries_before;
+    if retries > 0 {
+        println!("{} requests retried", retries);
+    }
+    Ok(())
+}
+
+/// Told after a line whose requests were retried since the count was
+/// `before`.
+fn retried(session: &dyn Calls, before: u64) -> String {
+    match session.retry_count() - before {
+        0 => String::new(),
+        n => format!(" (retried {} times)", n),
+    }
+}
+
+/// What became of a `115://` link given to [`Runtime::import`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportOutcome {
+    /// 115 had the file, it is now in the folder
+    Imported,
+    /// the folder already holds the file
+    AlreadyExists,
+    /// 115 does not have the file (rapid upload status 1), it has to be
+    /// uploaded for real
+    NotIn115,
+    /// name not allowed by 115
+    ForbiddenName,
+}
+
+impl std::fmt::Display for ImportOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportOutcome::Imported => write!(f, "imported"),
+            ImportOutcome::AlreadyExists => write!(f, "already exists"),
+            ImportOutcome::NotIn115 => write!(f, "NOT IN 115"),
+            ImportOutcome::ForbiddenName => write!(f, "NAME NOT ALLOW"),
         }
+    }
+}
 
-        if session.delete_one(0, folder_id).is_err() {
-            eprintln!("fail to delete the folder TMP_rs115");
+/// How many links of an import ended how.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    pub imported: usize,
+    pub already_exists: usize,
+    pub not_in_115: usize,
+    pub forbidden: usize,
+    /// invalid links and failed uploads
+    pub failed: usize,
+    /// requests made again after a network error or a rate limit
+    pub retries: u64,
+}
+
+impl ImportSummary {
+    fn add(&mut self, outcome: ImportOutcome) {
+        match outcome {
+            ImportOutcome::Imported => self.imported += 1,
+            ImportOutcome::AlreadyExists => self.already_exists += 1,
+            ImportOutcome::NotIn115 => self.not_in_115 += 1,
+            ImportOutcome::ForbiddenName => self.forbidden += 1,
+        }
+    }
+
+    /// Whether every link is in the folder now.
+    pub fn is_complete(&self) -> bool {
+        self.not_in_115 + self.forbidden + self.failed == 0
+    }
+}
+
+impl std::fmt::Display for ImportSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} imported, {} already there, {} not in 115, {} forbidden, {} failed",
+            self.imported, self.already_exists, self.not_in_115, self.forbidden, self.failed
+        )?;
+        if self.retries > 0 {
+            write!(f, "\n{} requests retried", self.retries)?;
         }
         Ok(())
     }
 }
 
-impl Default for Runtime {
-    fn default() -> Self {
-        Self::new()
+/// What [`Runtime::import_tree`] did.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TreeImportSummary {
+    pub folders_created: usize,
+    /// folders that were already there
+    pub folders_reused: usize,
+    /// folders that could not be created, their files count as failed
+    pub folders_failed: usize,
+    pub files: ImportSummary,
+}
+
+impl TreeImportSummary {
+    /// Whether every folder and file of the tree is in 115 now.
+    pub fn is_complete(&self) -> bool {
+        self.folders_failed == 0 && self.files.is_complete()
     }
 }
 
-#[derive(Debug)]
-pub(crate) struct UnknownError();
-impl std::fmt::Display for UnknownError {
+impl std::fmt::Display for TreeImportSummary {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "check fails")
+        write!(
+            f,
+            "folders: {} created, {} already there, {} failed\nfiles: {}",
+            self.folders_created, self.folders_reused, self.folders_failed, self.files
+        )
+    }
+}
+
+/// The cid of `to`, a cid or a folder path.
+fn resolve_folder(session: &dyn Calls, to: &str) -> Result<u64> {
+    match to.trim().parse() {
+        Ok(cid) => Ok(cid),
+        Err(_) => session.folder_id(to.trim()),
+    }
+}
+
+fn import_one(session: &dyn Calls, link: &Sha1Link, cid: u64) -> Result<ImportOutcome> {
+    match session.rapid_upload(&link.to_request(cid)) {
+        Ok(()) => Ok(ImportOutcome::Imported),
+        Err(Error::FileExists) => Ok(ImportOutcome::AlreadyExists),
+        Err(Error::RapidUpload { status: 1, .. }) => Ok(ImportOutcome::NotIn115),
+        Err(Error::ForbiddenName) => Ok(ImportOutcome::ForbiddenName),
+        Err(e) => Err(e),
+    }
+}
+
+/// Create folder `name` in `pid`, or find the one already there. Returns
+/// its cid and whether it was created.
+fn make_folder(session: &dyn Calls, pid: u64, name: &str) -> Result<(u64, bool)> {
+    match session.create_folder(pid, name.to_owned()) {
+        Ok(cid) => Ok((cid, true)),
+        Err(Error::DirExist) => match session.find_folder(pid, name)? {
+            Some(cid) => Ok((cid, false)),
+            // a file holds the name
+            None => Err(Error::DirExist),
+        },
+        Err(e) => Err(e),
+    }
+}
+
+struct TreeImport {
+    pause_on_verification: bool,
+    summary: TreeImportSummary,
+}
+
+impl TreeImport {
+    fn run(&mut self, session: &dyn Calls, tree: &Sha1JsonFolderEntity, to: &str) -> Result<()> {
+        let retries_before = session.retry_count();
+        let cid = resolve_folder(session, to)?;
+        // a tree without a name is imported right into `to`
+        let res = if tree.dir_name.is_empty() {
+            self.content(session, tree, cid, "")
+        } else {
+            self.folder(session, tree, cid, "")
+        };
+        self.summary.files.retries = session.retry_count() - retries_before;
+        res
+    }
+
+    /// Make `folder` in `pid`, then import what it holds into it.
+    fn folder(
+        &mut self,
+        session: &dyn Calls,
+        folder: &Sha1JsonFolderEntity,
+        pid: u64,
+        parent: &str,
+    ) -> Result<()> {
+        let path = format!("{}/{}", parent, folder.dir_name);
+        let cid = match self.verified(|| make_folder(session, pid, &folder.dir_name)) {
+            Ok((cid, true)) => {
+                println!("created folder {}", path);
+                self.summary.folders_created += 1;
+                cid
+            }
+            Ok((cid, false)) => {
+                println!("folder already there {}", path);
+                self.summary.folders_reused += 1;
+                cid
+            }
+            Err(e @ Error::VerificationRequired { .. }) => return Err(e),
+            Err(e) => {
+                println!("failed to create folder: {}, cause by: {}", path, e);
+                self.summary.folders_failed += 1;
+                self.summary.files.failed += count_files(folder);
+                return Ok(());
+            }
+        };
+        self.content(session, folder, cid, &path)
+    }
+
+    /// Import the files and folders of `folder` into `cid`.
+    fn content(
+        &mut self,
+        session: &dyn Calls,
+        folder: &Sha1JsonFolderEntity,
+        cid: u64,
+        path: &str,
+    ) -> Result<()> {
+        for line in &folder.files {
+            let link: Sha1Link = match line.parse() {
+                Ok(link) => link,
+                Err(e) => {
+                    println!("{} in {}/: {}", e, path, line);
+                    self.summary.files.failed += 1;
+                    continue;
+                }
+            };
+            let retries = session.retry_count();
+            let res = self.verified(|| import_one(session, &link, cid));
+            let retried = retried(session, retries);
+            match res {
+                Ok(outcome) => {
+                    println!("{}: {}/{}{}", outcome, path, link.name, retried);
+                    self.summary.files.add(outcome);
+                }
+                Err(e @ Error::VerificationRequired { .. }) => return Err(e),
+                Err(e) => {
+                    println!(
+                        "failed to import: {}/{}, cause by: {}{}",
+                        path, link.name, e, retried
+                    );
+                    self.summary.files.failed += 1;
+                }
+            }
+        }
+        for dir in &folder.dirs {
+            self.folder(session, dir, cid, path)?;
+        }
+        Ok(())
+    }
+
+    /// `call`, made again each time 115 asks for a human verification and
+    /// the user passes it.
+    fn verified<T>(&self, mut call: impl FnMut() -> Result<T>) -> Result<T> {
+        loop {
+            match call() {
+                Err(Error::VerificationRequired { message })
+                    if wait_for_verification(
+                        &message,
+                        self.pause_on_verification,
+                        "run import-tree again to go on, what is done is kept",
+                    ) => {}
+                res => return res,
+            }
+        }
+    }
+}
+
+/// Links in `folder` and its sub folders.
+fn count_files(folder: &Sha1JsonFolderEntity) -> usize {
+    folder.files.len() + folder.dirs.iter().map(count_files).sum::<usize>()
+}
+
+fn import_bulk<T: BufRead, U: Write>(
+    session: &dyn Calls,
+    links: T,
+    to: &str,
+    mut failed: Option<U>,
+    pause_on_verification: bool,
+) -> Result<ImportSummary> {
+    let retries_before = session.retry_count();
+    let cid = resolve_folder(session, to)?;
+    let mut summary = ImportSummary::default();
+    let mut lines = links.lines();
+    // a line to import again after a human verification
+    let mut pending = None;
+    loop {
+        let line = match pending.take() {
+            Some(line) => line,
+            None => match lines.next() {
+                Some(line) => line?,
+                None => break,
+            },
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let link: Sha1Link = match line.parse() {
+            Ok(link) => link,
+            Err(e) => {
+                println!("{}: {}", e, line);
+                summary.failed += 1;
+                if let Some(ref mut failed) = failed {
+                    writeln!(failed, "{}", line)?;
+                }
+                continue;
+            }
+        };
+        let retries = session.retry_count();
+        let res = import_one(session, &link, cid);
+        let retried = retried(session, retries);
+        let in_folder = match res {
+            Ok(outcome) => {
+                println!("{}: {}{}", outcome, link.name, retried);
+                summary.add(outcome);
+                matches!(
+                    outcome,
+                    ImportOutcome::Imported | ImportOutcome::AlreadyExists
+                )
+            }
+            Err(Error::VerificationRequired { message }) => {
+                let stopped = stopped_bulk(failed.is_some());
+                if wait_for_verification(&message, pause_on_verification, stopped) {
+                    pending = Some(line);
+                    continue;
+                }
+                // stopped: keep what is left so the job can be resumed
+                if let Some(ref mut failed) = failed {
+                    writeln!(failed, "{}", line)?;
+                    for line in lines {
+                        writeln!(failed, "{}", line?)?;
+                    }
+                }
+                return Err(Error::VerificationRequired { message });
+            }
+            Err(e) => {
+                println!(
+                    "failed to import: {}, cause by: {}{}",
+                    link.name, e, retried
+                );
+                summary.failed += 1;
+                false
+            }
+        };
+        if !in_folder {
+            if let Some(ref mut failed) = failed {
+                writeln!(failed, "{}", line)?;
+            }
+        }
+    }
+    summary.retries = session.retry_count() - retries_before;
+    Ok(summary)
+}
+
+/// A 5 byte file 115 already has, picked at random from known hashes, named
+/// `name`: its rapid upload succeeds (status 2) unless 115 refuses the name.
+/// The copy lands in the temporary folder `cid`, deleted after the check.
+fn probe(name: &str, cid: u64) -> RapidUploadRequest {
+    let hash = get_a_hash();
+    RapidUploadRequest {
+        filename: name.to_owned(),
+        file_size: 5,
+        sha1: hash.clone(),
+        block_sha1: hash,
+        cid,
+    }
+}
+
+/// The calls checks and imports make, by a local session or through the agent.
+trait Calls {
+    fn create_folder(&self, pid: u64, name: String) -> Result<u64>;
+    fn folder_id(&self, path: &str) -> Result<u64>;
+    fn find_folder(&self, pid: u64, name: &str) -> Result<Option<u64>>;
+    fn delete_one(&self, pid: u64, target: u64) -> Result<()>;
+    fn rapid_upload(&self, req: &RapidUploadRequest) -> Result<()>;
+    /// retries so far, reported by the bulk check and imports
+    fn retry_count(&self) -> u64 {
+        0
+    }
+    /// see [`RateLimiter::throttle_factor`]
+    fn throttle_factor(&self) -> f64 {
+        1.0
+    }
+}
+
+impl Calls for &Session {
+    fn create_folder(&self, pid: u64, name: String) -> Result<u64> {
+        Session::create_folder(self, pid, name)
+    }
+
+    fn folder_id(&self, path: &str) -> Result<u64> {
+        Session::folder_id(self, path)
+    }
+
+    fn find_folder(&self, pid: u64, name: &str) -> Result<Option<u64>> {
+        Session::find_folder(self, pid, name)
+    }
+
+    fn delete_one(&self, pid: u64, target: u64) -> Result<()> {
+        Session::delete_one(self, pid, target)
+    }
+
+    fn rapid_upload(&self, req: &RapidUploadRequest) -> Result<()> {
+        Session::rapid_upload(self, req)
+    }
+
+    fn retry_count(&self) -> u64 {
+        Session::retry_count(self)
+    }
+
+    fn throttle_factor(&self) -> f64 {
+        self.rate_limiter().throttle_factor()
+    }
+}
+
+/// A profile of the agent; retries and throttling happen in the agent, which
+/// tells how many requests it retried.
+#[cfg(all(unix, feature = "agent"))]
+struct AgentProfile<'a> {
+    agent: &'a AgentClient,
+    profile: &'a str,
+}
+
+#[cfg(all(unix, feature = "agent"))]
+impl Calls for AgentProfile<'_> {
+    fn create_folder(&self, pid: u64, name: String) -> Result<u64> {
+        self.agent.create_folder(Some(self.profile), pid, name)
+    }
+
+    fn folder_id(&self, path: &str) -> Result<u64> {
+        self.agent.folder_id(Some(self.profile), path)
+    }
+
+    fn find_folder(&self, pid: u64, name: &str) -> Result<Option<u64>> {
+        self.agent.find_folder(Some(self.profile), pid, name)
+    }
+
+    fn delete_one(&self, pid: u64, target: u64) -> Result<()> {
+        self.agent
+            .delete_bulk(Some(self.profile), pid, vec![target])
+    }
+
+    fn rapid_upload(&self, req: &RapidUploadRequest) -> Result<()> {
+        self.agent.rapid_upload(Some(self.profile), req)
+    }
+
+    fn retry_count(&self) -> u64 {
+        self.agent.retry_count()
+    }
+}
+
+/// What bulk jobs do with what is left when they stop for a verification,
+/// `kept` when they were given a failed list.
+fn stopped_bulk(kept: bool) -> &'static str {
+    if kept {
+        "what is left is kept in the failed list"
+    } else {
+        "what is left is not kept, give a failed list to resume later"
+    }
+}
+
+/// Tell the user 115 wants a captcha solved and, if `pause`, wait for them
+/// to do it. Returns whether to go on; when not, `stopped` tells what to do.
+fn wait_for_verification(message: &str, pause: bool, stopped: &str) -> bool {
+    eprintln!(
+        "115 asks for a human verification ({}): open https://115.com in a browser and pass it",
+        message
+    );
+    if !pause {
+        eprintln!("stopping, {}", stopped);
+        return false;
     }
+    eprint!("press Enter to resume, Ctrl-C to stop ");
+    let mut answer = String::new();
+    matches!(std::io::stdin().read_line(&mut answer), Ok(n) if n > 0)
 }
-impl std::error::Error for UnknownError {}
@@ -0,0 +1,309 @@
+//! `115://` links of local files.
+//!
+//! A link holds the size, the sha1 of the whole file and the sha1 of its
+//! first 128KiB, which is what 115 asks for a rapid upload. Both sha1s are
+//! computed in the same read of the file.
+//!
+//! [`hash_files`] hashes many files on several threads, for libraries too big
+//! to wait on one file after the other.
+use crypto::digest::Digest;
+use crypto::sha1::Sha1;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::{Error, Result};
+use crate::parsers::Sha1Link;
+
+/// Bytes the block sha1 covers.
+pub const BLOCK_SIZE: u64 = 128 * 1024;
+const BUFFER_SIZE: usize = 256 * 1024;
+/// Most threads used by default, more rarely helps a disk.
+const MAX_DEFAULT_THREADS: usize = 8;
+/// How often the progress of [`hash_files`] is reported while files are read.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+/// How many files [`hash_files`] reads at once, and with how big reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HashConfig {
+    /// files hashed in parallel, one thread each
+    pub threads: usize,
+    /// bytes of each read
+    pub buffer_size: usize,
+}
+
+impl Default for HashConfig {
+    fn default() -> Self {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        Self {
+            threads: threads.min(MAX_DEFAULT_THREADS),
+            buffer_size: BUFFER_SIZE,
+        }
+    }
+}
+
+/// How far a [`hash_files`] run is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    /// files done, hashed or failed
+    pub files: usize,
+    pub total_files: usize,
+    /// bytes read
+    pub bytes: u64,
+    /// sizes of the files when the run started
+    pub total_bytes: u64,
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    /// Bytes read per second so far.
+    pub fn throughput(&self) -> f64 {
+        match self.elapsed.as_secs_f64() {
+            secs if secs > 0.0 => self.bytes as f64 / secs,
+            _ => 0.0,
+        }
+    }
+}
+
+/// Size and sha1s of some content, upper case like in links.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileHashes {
+    pub size: u64,
+    pub sha1: String,
+    /// sha1 of the first 128KiB, of everything for smaller files
+    pub block_sha1: String,
+}
+
+impl FileHashes {
+    pub fn into_link(self, name: impl Into<String>) -> Sha1Link {
+        Sha1Link {
+            name: name.into(),
+            size: self.size,
+            sha1: self.sha1,
+            block_sha1: self.block_sha1,
+        }
+    }
+}
+
+/// The link of the file at `path`, named like the file.
+pub fn hash_file(path: impl AsRef<Path>) -> Result<Sha1Link> {
+    hash_path(path.as_ref(), &mut vec![0; BUFFER_SIZE], |_| {})
+}
+
+/// Size and sha1s of everything `reader` yields.
+pub fn hash_reader(reader: impl Read) -> io::Result<FileHashes> {
+    hash_stream(reader, &mut vec![0; BUFFER_SIZE], |_| {})
+}
+
+/// The links of `paths`, in the same order, hashed on `config.threads`
+/// threads. `progress` is called on the calling thread every 200ms and once
+/// more when every file is done.
+pub fn hash_files<P: AsRef<Path> + Sync>(
+    paths: &[P],
+    config: &HashConfig,
+    mut progress: impl FnMut(&Progress),
+) -> Vec<Result<Sha1Link>> {
+    let start = Instant::now();
+    let total_bytes = paths
+        .iter()
+        .filter_map(|path| fs::metadata(path).ok())
+        .map(|metadata| metadata.len())
+        .sum();
+    let next = AtomicUsize::new(0);
+    let bytes = AtomicU64::new(0);
+    let mut results: Vec<Option<Result<Sha1Link>>> = paths.iter().map(|_| None).collect();
+    let mut files = 0;
+    let report = |files| Progress {
+        files,
+        total_files: paths.len(),
+        bytes: bytes.load(Ordering::Relaxed),
+        total_bytes,
+        elapsed: start.elapsed(),
+    };
+
+    thread::scope(|scope| {
+        let (tx, rx) = mpsc::channel();
+        for _ in 0..config.threads.clamp(1, paths.len().max(1)) {
+            let (tx, next, bytes) = (tx.clone(), &next, &bytes);
+            scope.spawn(move || {
+                let mut buf = vec![0; config.buffer_size.max(1)];
+                // each thread takes the next file not taken yet
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(path) = paths.get(i) else {
+                        break;
+                    };
+                    let res = hash_path(path.as_ref(), &mut buf, |n| {
+                        bytes.fetch_add(n as u64, Ordering::Relaxed);
+                    });
+                    if tx.send((i, res)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(tx);
+
+        let mut reported = Instant::now();
+        loop {
+            match rx.recv_timeout(PROGRESS_INTERVAL) {
+                Ok((i, res)) => {
+                    results[i] = Some(res);
+                    files += 1;
+                    if reported.elapsed() < PROGRESS_INTERVAL {
+                        continue;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            progress(&report(files));
+            reported = Instant::now();
+        }
+    });
+    progress(&report(files));
+    results
+        .into_iter()
+        .map(|res| res.expect("every file is hashed"))
+        .collect()
+}
+
+/// The link of the file at `path`, read with `buf`; `read` is told the size
+/// of every read.
+fn hash_path(path: &Path, buf: &mut [u8], read: impl FnMut(usize)) -> Result<Sha1Link> {
+    let name = link_name(path)?;
+    if path.is_dir() {
+        return Err(invalid(format!("{} is a directory", path.display())));
+    }
+    let hashes = hash_stream(File::open(path)?, buf, read)?;
+    Ok(hashes.into_link(name))
+}
+
+fn hash_stream(
+    mut reader: impl Read,
+    buf: &mut [u8],
+    mut read: impl FnMut(usize),
+) -> io::Result<FileHashes> {
+    let mut sha1 = Sha1::new();
+    let mut block_sha1 = Sha1::new();
+    let mut size = 0;
+    loop {
+        let n = match reader.read(buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        let in_block = BLOCK_SIZE.saturating_sub(size).min(n as u64) as usize;
+        block_sha1.input(&buf[..in_block]);
+        sha1.input(&buf[..n]);
+        size += n as u64;
+        read(n);
+    }
+    Ok(FileHashes {
+        size,
+        sha1: sha1.result_str().to_uppercase(),
+        block_sha1: block_sha1.result_str().to_uppercase(),
+    })
+}
+
+/// The file name of `path`, if a link can hold it.
+fn link_name(path: &Path) -> Result<String> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| invalid(format!("{} has no file name", path.display())))?;
+    let name = name
+        .to_str()
+        .ok_or_else(|| invalid(format!("{} is not a utf-8 name", path.display())))?;
+    if name.contains(['|', '\n', '\r']) {
+        return Err(invalid(format!(
+            "{} can not be in a 115 link, its name holds '|' or a line break",
+            path.display()
+        )));
+    }
+    Ok(name.to_owned())
+}
+
+fn invalid(message: String) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::InvalidInput, message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checkers::is_valid_sha1_line;
+
+    #[test]
+    fn hash_test() {
+        let empty = hash_reader(&b""[..]).unwrap();
+        assert_eq!(empty.size, 0);
+        assert_eq!(empty.sha1, "DA39A3EE5E6B4B0D3255BFEF95601890AFD80709");
+        assert_eq!(empty.block_sha1, empty.sha1);
+
+        // the block sha1 stops at 128KiB, even inside a read
+        let data: Vec<u8> = (0..BLOCK_SIZE as usize * 3 + 7).map(|i| i as u8).collect();
+        let hashes = hash_reader(&data[..]).unwrap();
+        let block = hash_reader(&data[..BLOCK_SIZE as usize]).unwrap();
+        assert_eq!(hashes.size, data.len() as u64);
+        assert_eq!(hashes.block_sha1, block.sha1);
+        assert_ne!(hashes.sha1, block.sha1);
+
+        let dir = std::env::temp_dir().join(format!("rs115-hash-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a file.bin");
+        std::fs::write(&path, &data).unwrap();
+        let link = hash_file(&path).unwrap();
+        assert_eq!(link.name, "a file.bin");
+        assert_eq!(link.sha1, hashes.sha1);
+        let line = link.to_string();
+        assert!(is_valid_sha1_line(&line));
+        assert_eq!(line.parse::<Sha1Link>().unwrap(), link);
+
+        assert!(hash_file(&dir).is_err());
+        let piped = dir.join("a|b");
+        std::fs::write(&piped, b"x").unwrap();
+        assert!(hash_file(&piped).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn hash_files_test() {
+        let dir = std::env::temp_dir().join(format!("rs115-hash-files-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut paths = Vec::new();
+        for i in 0..9 {
+            let path = dir.join(format!("{}.bin", i));
+            let data: Vec<u8> = (0..i * 70_001).map(|b| (b * i) as u8).collect();
+            std::fs::write(&path, data).unwrap();
+            paths.push(path);
+        }
+        paths.insert(4, dir.join("missing.bin"));
+
+        // reads that do not end on the block
+        let config = HashConfig {
+            threads: 3,
+            buffer_size: 10_000,
+        };
+        let mut last = None;
+        let links = hash_files(&paths, &config, |progress| last = Some(*progress));
+        assert_eq!(links.len(), paths.len());
+        for (path, link) in paths.iter().zip(&links) {
+            match hash_file(path) {
+                Ok(expected) => assert_eq!(link.as_ref().unwrap(), &expected),
+                Err(_) => assert!(link.is_err()),
+            }
+        }
+        let last = last.unwrap();
+        assert_eq!((last.files, last.total_files), (10, 10));
+        assert_eq!(last.bytes, 36 * 70_001);
+        assert_eq!(last.bytes, last.total_bytes);
+
+        let none: Vec<&Path> = Vec::new();
+        assert!(hash_files(&none, &HashConfig::default(), |_| {}).is_empty());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,5 +1,66 @@
+//! Quick actions on 115.com, as a library and as the `rs115` binary.
+//!
+//! [`Session`] signs and sends the 115 api calls for one account,
+//! [`Runtime`] adds the cookie cache and the bulk name checks used by the cli.
+//!
+//! Cargo features:
+//! - `blocking` (default): `Session` and `Runtime` on the blocking reqwest client.
+//! - `async`: [`AsyncSession`] on the non-blocking reqwest client.
+//! - `agent`: the unix socket agent holding the unlocked profiles, implies
+//!   `blocking`.
+//! - `cli` (default): the `rs115` binary, implies `blocking` and `agent`.
+//!
+//! Without features only the client independent core builds: link and tree
+//! types, hashing of local files and directories into links and trees,
+//! checkers, cookie parsing, signing and response parsing.
+#[cfg(all(unix, feature = "agent"))]
+pub mod agent;
+pub mod api;
+#[cfg(feature = "async")]
+pub mod async_upload;
 pub mod checkers;
+pub mod client;
+pub mod cookies;
+#[cfg(feature = "blocking")]
 pub mod dummies;
+pub mod encryption;
+pub mod endpoints;
+pub mod error;
+pub mod export;
+#[cfg(feature = "blocking")]
 pub mod functions;
+pub mod hash;
+pub mod network;
 pub mod parsers;
+pub mod profiles;
+#[cfg(feature = "blocking")]
+pub mod qrlogin;
+pub mod ratelimit;
+pub mod redact;
+pub mod retry;
+pub mod store;
+#[cfg(feature = "blocking")]
 pub mod upload;
+
+#[cfg(all(unix, feature = "agent"))]
+pub use agent::{Agent, AgentClient};
+pub use api::{AccountInfo, QrStatus, QrToken, RapidUploadRequest, SessionBuilder, SessionConfig};
+#[cfg(feature = "async")]
+pub use async_upload::AsyncSession;
+pub use client::ClientIdentity;
+pub use cookies::CookieJar;
+pub use encryption::Encryption;
+pub use endpoints::Endpoints;
+pub use error::{Error, Result};
+#[cfg(feature = "blocking")]
+pub use functions::{ImportOutcome, ImportSummary, Runtime, TreeImportSummary};
+pub use network::NetworkConfig;
+pub use parsers::{Sha1JsonFolderEntity, Sha1Link};
+pub use profiles::{Profile, ProfileCache};
+#[cfg(feature = "blocking")]
+pub use qrlogin::QrLogin;
+pub use ratelimit::{RateLimit, RateLimitConfig, RateLimiter};
+pub use retry::RetryPolicy;
+pub use store::{EnvStore, FileStore, MemoryStore, SessionStore};
+#[cfg(feature = "blocking")]
+pub use upload::Session;
@@ -1,28 +1,98 @@
 use std::fs::File;
-use std::io::BufReader;
-use std::path::Path;
+use std::io::IsTerminal;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use clap::ArgMatches;
+use qrcode::render::unicode;
+use qrcode::{Color, QrCode};
+use rs115::endpoints::Endpoints;
+use rs115::export::{self, ExportOptions};
 use rs115::functions::*;
+use rs115::hash::{hash_files, HashConfig, Progress};
+use rs115::store::{config_dir, default_file_store, default_store, FileStore, COOKIES_ENV};
+#[cfg(unix)]
+use rs115::{Agent, AgentClient};
+use rs115::{
+    ClientIdentity, Encryption, EnvStore, Error, RateLimit, SessionConfig, SessionStore,
+    Sha1JsonFolderEntity,
+};
+use serde::Deserialize;
 fn app() -> i32 {
-    let mut rt = Runtime::new();
-
     use clap::{load_yaml, App};
     let yaml = load_yaml!("cli.yml");
     let matches = App::from_yaml(yaml).get_matches();
+    init_logging(&matches);
 
-    // clean subcommand
-    if matches.subcommand_matches("clean").is_some() && rt.clean().is_err() {
-        eprintln!("clean failed, loggin info not found or unable to delete...");
-        return 1;
+    let config = match parse_config(&matches) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+
+    // hash subcommand, local only
+    if let Some(matches) = matches.subcommand_matches("hash") {
+        return hash(matches);
+    }
+
+    // export-tree subcommand, local only
+    if let Some(matches) = matches.subcommand_matches("export-tree") {
+        return export_tree(matches);
+    }
+
+    // clean subcommand, works without the passphrase of an encrypted cache
+    if matches.subcommand_matches("clean").is_some() {
+        if let Err(e) = default_store().clear() {
+            eprintln!("clean failed: {}", e);
+            return 1;
+        }
+        return 0;
+    }
+
+    // cache subcommand
+    if let Some(matches) = matches.subcommand_matches("cache") {
+        return cache(matches);
+    }
+
+    // agent subcommand
+    if let Some(matches) = matches.subcommand_matches("agent") {
+        return agent(config, matches);
+    }
+
+    let mut rt = match open_runtime(config, &matches) {
+        Ok(rt) => rt,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+
+    // profiles subcommand
+    if let Some(matches) = matches.subcommand_matches("profiles") {
+        return profiles(&mut rt, matches);
+    }
+
+    // login subcommand
+    if let Some(matches) = matches.subcommand_matches("login") {
+        return login(&mut rt, matches);
     }
 
     // set-cookies subcommand
     if let Some(matches) = matches.subcommand_matches("set-cookies") {
         let cookies = matches.value_of("cookies").unwrap();
-        if rt.set_cookies(cookies).is_err() {
-            eprintln!("set_cookies failed, program was not able to write to files ");
-            return 1;
+        match rt.set_cookies(cookies) {
+            Ok(warnings) => {
+                for warning in warnings {
+                    eprintln!("warning: {}", warning);
+                }
+            }
+            Err(e) => {
+                eprintln!("set_cookies failed, {}", e);
+                return 1;
+            }
         }
     }
 
@@ -52,34 +122,57 @@ fn app() -> i32 {
                 }
             }
 
-            let file = File::open(file).unwrap();
-            let file = BufReader::new(file);
-            if rt
-                .check_name_bulk_to_file(file, forbiden_list, failed_list, interval)
-                .is_err()
-            {
+            let file = match File::open(file) {
+                Ok(f) => BufReader::new(f),
+                Err(e) => {
+                    eprintln!("fail to open file: {}, {}", file, e);
+                    return 1;
+                }
+            };
+            if let Err(e) = rt.check_name_bulk_to_file(file, forbiden_list, failed_list, interval) {
+                eprintln!("{}", e);
                 return 1;
             }
         } else {
             let name = matches.value_of("name").unwrap();
-            if let Ok(is_valid) = rt.check_name(name) {
-                if is_valid {
+            match rt.check_name(name) {
+                Ok(true) => {
                     println!("name is VALID");
                     return 0;
-                } else {
+                }
+                Ok(false) => {
                     println!("name is NOT valid");
                     return 2;
                 }
-            } else {
-                eprintln!("fail to check {}", name);
-                return 1;
+                Err(e) => {
+                    eprintln!("fail to check {}, {}", name, e);
+                    return 1;
+                }
             }
         }
     }
 
+    // import subcommand
+    if let Some(matches) = matches.subcommand_matches("import") {
+        return import(&mut rt, matches);
+    }
+
+    // import-tree subcommand
+    if let Some(matches) = matches.subcommand_matches("import-tree") {
+        return import_tree(&mut rt, matches);
+    }
+
     // status
     if let Some(matches) = matches.subcommand_matches("status") {
         if matches.is_present("cookies") {
+            #[cfg(unix)]
+            if let Some(agent) = rt.agent() {
+                println!(
+                    "cookies are held by the agent at {}",
+                    agent.path().display()
+                );
+                return 0;
+            }
             if rt.has_cookies() {
                 println!("{}", rt.print_cookies());
             } else {
@@ -87,15 +180,910 @@ fn app() -> i32 {
             }
         } else if matches.is_present("session") {
             println!("{:#?}", rt);
-        } else if rt.has_cookies() {
-            println!("You are in! (cookies may expire anytime!)");
         } else {
+            return status(&rt);
+        }
+    }
+    0
+}
+
+fn hash(matches: &ArgMatches) -> i32 {
+    let config = match parse_hashing(matches) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+    let paths: Vec<&str> = matches.values_of("paths").unwrap().collect();
+    let links = hash_files(&paths, &config, show_progress);
+    end_progress();
+    let mut rc = 0;
+    for (path, link) in paths.iter().zip(links) {
+        match link {
+            Ok(link) => println!("{}", link),
+            Err(e) => {
+                eprintln!("fail to hash {}, {}", path, e);
+                rc = 1;
+            }
+        }
+    }
+    rc
+}
+
+fn export_tree(matches: &ArgMatches) -> i32 {
+    let mut output: Option<File> = None;
+    let rc = parse_optional_path(matches, "output", &mut output);
+    if rc != 0 {
+        return rc;
+    }
+    let globs = |name| -> Vec<String> {
+        matches
+            .values_of(name)
+            .map(|globs| globs.map(str::to_owned).collect())
+            .unwrap_or_default()
+    };
+    let options = ExportOptions {
+        include: globs("include"),
+        exclude: globs("exclude"),
+        symlinks: matches.value_of("symlinks").unwrap().parse().unwrap(),
+        hashing: match parse_hashing(matches) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("{}", e);
+                return 1;
+            }
+        },
+    };
+    let dir = matches.value_of("dir").unwrap();
+    let export = export::export_tree_with_progress(dir, &options, show_progress);
+    end_progress();
+    let export = match export {
+        Ok(export) => export,
+        Err(e) => {
+            eprintln!("fail to export {}, {}", dir, e);
+            return 1;
+        }
+    };
+    for (path, e) in &export.failed {
+        eprintln!("fail to hash {}, {}", path.display(), e);
+    }
+
+    let json = serde_json::to_string_pretty(&export.tree).expect("trees are plain json");
+    let written = match output {
+        Some(mut file) => writeln!(file, "{}", json),
+        None => writeln!(std::io::stdout(), "{}", json),
+    };
+    if let Err(e) = written {
+        eprintln!("fail to write the tree, {}", e);
+        return 1;
+    }
+    eprintln!(
+        "{} files, {} exported, {} failed",
+        export.files,
+        format_size(export.size),
+        export.failed.len()
+    );
+    if export.failed.is_empty() {
+        0
+    } else {
+        2
+    }
+}
+
+fn import(rt: &mut Runtime, matches: &ArgMatches) -> i32 {
+    let mut failed_list: Option<File> = None;
+    let rc = parse_optional_path(matches, "output_failed_case", &mut failed_list);
+    if rc != 0 {
+        return rc;
+    }
+    let links: Box<dyn BufRead> = match matches.value_of("file") {
+        Some(path) => match File::open(path) {
+            Ok(f) => Box::new(BufReader::new(f)),
+            Err(e) => {
+                eprintln!("fail to open file: {}, {}", path, e);
+                return 1;
+            }
+        },
+        None => {
+            let links: Vec<&str> = matches.values_of("links").unwrap_or_default().collect();
+            Box::new(std::io::Cursor::new(links.join("\n")))
+        }
+    };
+    let to = matches.value_of("to").unwrap();
+    match rt.import(links, to, failed_list) {
+        Ok(summary) => {
+            println!("{}", summary);
+            if summary.is_complete() {
+                0
+            } else {
+                2
+            }
+        }
+        Err(e) => {
+            eprintln!("import failed, {}", e);
+            1
+        }
+    }
+}
+
+fn import_tree(rt: &mut Runtime, matches: &ArgMatches) -> i32 {
+    let path = matches.value_of("tree").unwrap();
+    let tree = match path.parse::<Sha1JsonFolderEntity>() {
+        Ok(tree) => tree,
+        Err(e) => {
+            eprintln!("fail to read tree: {}, {}", path, e);
+            return 1;
+        }
+    };
+    let to = matches.value_of("to").unwrap();
+    match rt.import_tree(&tree, to) {
+        Ok(summary) => {
+            println!("{}", summary);
+            if summary.is_complete() {
+                0
+            } else {
+                2
+            }
+        }
+        Err(e) => {
+            eprintln!("import-tree failed, {}", e);
+            1
+        }
+    }
+}
+
+fn login(rt: &mut Runtime, matches: &ArgMatches) -> i32 {
+    let poll = match matches.value_of("poll").map(str::parse::<u64>) {
+        None => Duration::from_millis(1000),
+        Some(Ok(t)) => Duration::from_millis(t),
+        Some(Err(_)) => {
+            eprintln!("poll must be positive numbers");
+            return 1;
+        }
+    };
+    let qr = match rt.qr_login() {
+        Ok(qr) => qr,
+        Err(e) => {
+            eprintln!("fail to get a login qr code, {}", e);
+            return 1;
+        }
+    };
+    let code = match QrCode::new(qr.qr_text()) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("fail to render the qr code, {}", e);
+            return 1;
+        }
+    };
+    println!("{}", render_qr(&code));
+    if let Some(path) = matches.value_of("png") {
+        if let Err(e) = write_qr_png(&code, path) {
+            eprintln!("fail to write {}: {}", path, e);
+            return 1;
+        }
+        println!("qr code written to {}", path);
+    }
+    println!("scan the qr code with the 115 app");
+
+    let cookies = match qr.wait(poll, |status| println!("{}", status)) {
+        Ok(cookies) => cookies,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+    match rt.set_cookies(&cookies) {
+        Ok(_) => {
+            println!("You are in!");
+            0
+        }
+        Err(e) => {
+            eprintln!("set_cookies failed, {}", e);
+            1
+        }
+    }
+}
+
+fn profiles(rt: &mut Runtime, matches: &ArgMatches) -> i32 {
+    let res = match matches.subcommand() {
+        ("list", _) => {
+            let cache = rt.profiles();
+            if cache.profiles.is_empty() {
+                println!("no profiles, run set-cookies or login first");
+            }
+            for (name, profile) in &cache.profiles {
+                let mark = if cache.default.as_deref() == Some(name.as_str()) {
+                    "*"
+                } else {
+                    " "
+                };
+                match profile.user_id {
+                    Some(ref id) => println!("{} {} (user id {})", mark, name, id),
+                    None => println!("{} {}", mark, name),
+                }
+            }
+            Ok(())
+        }
+        ("use", Some(m)) => rt.use_profile(m.value_of("name").unwrap()),
+        ("remove", Some(m)) => rt.remove_profile(m.value_of("name").unwrap()),
+        ("client", Some(m)) => {
+            return match client(rt, m) {
+                Ok(()) => 0,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    1
+                }
+            }
+        }
+        _ => Ok(()),
+    };
+    match res {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+/// Show the client of the profile in use, or set it from a preset and the
+/// fields given.
+fn client(rt: &mut Runtime, matches: &ArgMatches) -> Result<(), String> {
+    let current = rt.client();
+    if matches.is_present("list") {
+        for name in ClientIdentity::preset_names() {
+            let preset = ClientIdentity::preset(name).unwrap();
+            let mark = if preset == current { "*" } else { " " };
+            println!("{} {} (app version {})", mark, name, preset.app_version);
+        }
+        return Ok(());
+    }
+    if matches.is_present("reset") {
+        return rt.set_client(None).map_err(|e| e.to_string());
+    }
+    let fields = ["preset", "app_version", "user_agent", "appid", "isp"];
+    if !fields.iter().any(|field| matches.is_present(field)) {
+        println!("preset: {}", current.preset_name().unwrap_or("none"));
+        println!("app version: {}", current.app_version);
+        println!("user agent: {}", current.user_agent);
+        println!("appid: {}", current.appid);
+        println!("isp: {}", current.isp);
+        return Ok(());
+    }
+
+    let mut client = match matches.value_of("preset") {
+        Some(name) => parse_client(name)?,
+        None => current,
+    };
+    if let Some(version) = matches.value_of("app_version") {
+        client = ClientIdentity {
+            appid: client.appid,
+            isp: client.isp,
+            ..ClientIdentity::desktop(version)
+        };
+    }
+    if let Some(user_agent) = matches.value_of("user_agent") {
+        client.user_agent = user_agent.to_owned();
+    }
+    if let Some(appid) = matches.value_of("appid") {
+        client.appid = appid.parse().map_err(|_| "appid must be a number")?;
+    }
+    if let Some(isp) = matches.value_of("isp") {
+        client.isp = isp.parse().map_err(|_| "isp must be a number")?;
+    }
+    rt.set_client(Some(client)).map_err(|e| e.to_string())
+}
+
+fn parse_client(name: &str) -> Result<ClientIdentity, String> {
+    ClientIdentity::preset(name).ok_or_else(|| {
+        let names: Vec<_> = ClientIdentity::preset_names().collect();
+        format!(
+            "unknown client preset {}, one of: {}",
+            name,
+            names.join(", ")
+        )
+    })
+}
+
+const PASSPHRASE_ENV: &str = "RS115_PASSPHRASE";
+const NEW_PASSPHRASE_ENV: &str = "RS115_NEW_PASSPHRASE";
+
+/// Through the agent at `$RS115_AGENT_SOCK` if set, else on the store.
+fn open_runtime(config: SessionConfig, matches: &ArgMatches) -> Result<Runtime, String> {
+    let profile = matches.value_of("profile");
+    #[cfg(unix)]
+    if let Some(agent) = AgentClient::from_env() {
+        return Runtime::with_agent(config, profile, agent).map_err(|e| e.to_string());
+    }
+    let store = open_store(matches)?;
+    Runtime::with_store(config, profile, store).map_err(|e| match e {
+        Error::HttpClient(_) | Error::Network(_) => e.to_string(),
+        e => format!("failed to read the cookie cache: {}", e),
+    })
+}
+
+#[cfg(unix)]
+fn agent(config: SessionConfig, matches: &ArgMatches) -> i32 {
+    use rs115::agent::{bind, default_socket_path, AGENT_SOCK_ENV};
+    use std::path::PathBuf;
+
+    let path = matches
+        .value_of("socket")
+        .map(PathBuf::from)
+        .unwrap_or_else(default_socket_path);
+    let res = || -> Result<(), String> {
+        if matches.is_present("stop") {
+            AgentClient::new(&path).stop().map_err(|e| e.to_string())?;
+            println!("agent stopped");
+            return Ok(());
+        }
+        let agent = match Agent::new(open_store(matches)?, config) {
+            Ok(agent) => agent,
+            Err(Error::NotLoggedIn) => {
+                return Err("no profiles, run set-cookies or login first".to_owned())
+            }
+            Err(e) => return Err(format!("failed to read the cookie cache: {}", e)),
+        };
+        let listener = bind(&path).map_err(|e| e.to_string())?;
+        println!(
+            "{}={}; export {};",
+            AGENT_SOCK_ENV,
+            path.display(),
+            AGENT_SOCK_ENV
+        );
+        if !matches.is_present("foreground") {
+            daemonize()?;
+        }
+        agent.serve(listener).map_err(|e| e.to_string())
+    };
+    match res() {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn agent(_: SessionConfig, _: &ArgMatches) -> i32 {
+    eprintln!("the agent needs unix sockets");
+    1
+}
+
+/// Go on in the background: the parent exits, the child leaves the terminal
+/// session with its stdio on /dev/null.
+#[cfg(unix)]
+fn daemonize() -> Result<(), String> {
+    use std::io::Write;
+    use std::os::unix::io::AsRawFd;
+
+    let null = File::options()
+        .read(true)
+        .write(true)
+        .open("/dev/null")
+        .map_err(|e| e.to_string())?;
+    std::io::stdout().flush().map_err(|e| e.to_string())?;
+    // SAFETY: only the main thread runs here, the sessions and their http
+    // client threads are built by the child on first use.
+    match unsafe { libc::fork() } {
+        -1 => {
+            return Err(format!(
+                "can not fork the agent: {}",
+                std::io::Error::last_os_error()
+            ))
+        }
+        0 => {}
+        _ => std::process::exit(0),
+    }
+    // SAFETY: plain syscalls on fds this process owns
+    unsafe {
+        libc::setsid();
+        for fd in 0..3 {
+            libc::dup2(null.as_raw_fd(), fd);
+        }
+    }
+    Ok(())
+}
+
+/// `$RS115_COOKIES`, else the cookie cache file, unlocked if encrypted.
+fn open_store(matches: &ArgMatches) -> Result<Box<dyn SessionStore>, String> {
+    if std::env::var_os(COOKIES_ENV).is_some() {
+        return Ok(Box::new(EnvStore::new()));
+    }
+    Ok(Box::new(unlock(default_file_store(), matches)?))
+}
+
+/// Give `store` the passphrase of its cache when it is encrypted.
+fn unlock(store: FileStore, matches: &ArgMatches) -> Result<FileStore, String> {
+    if !store.is_encrypted().map_err(|e| e.to_string())? {
+        return Ok(store);
+    }
+    let passphrase = passphrase(
+        matches.value_of("passphrase_fd"),
+        PASSPHRASE_ENV,
+        "cookie cache passphrase: ",
+        false,
+    )?;
+    Ok(store.encrypt(Encryption::new(passphrase)))
+}
+
+fn cache(matches: &ArgMatches) -> i32 {
+    let res = || -> Result<String, String> {
+        if std::env::var_os(COOKIES_ENV).is_some() {
+            return Err(format!(
+                "cookies come from ${}, there is no cache",
+                COOKIES_ENV
+            ));
+        }
+        let store = default_file_store();
+        let cache = unlock(store.clone(), matches)?
+            .load()
+            .map_err(|e| e.to_string())?;
+        let path = store.path().display().to_string();
+        match matches.subcommand() {
+            ("encrypt", Some(m)) => {
+                let passphrase = passphrase(
+                    m.value_of("new_passphrase_fd"),
+                    NEW_PASSPHRASE_ENV,
+                    "new passphrase: ",
+                    true,
+                )?;
+                let store = store.encrypt(Encryption::new(passphrase));
+                store.save(&cache).map_err(|e| e.to_string())?;
+                Ok(format!("{} encrypted", path))
+            }
+            _ => {
+                store.save(&cache).map_err(|e| e.to_string())?;
+                Ok(format!("{} decrypted", path))
+            }
+        }
+    };
+    match res() {
+        Ok(done) => {
+            println!("{}", done);
+            0
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+/// A passphrase from the file descriptor `fd`, else `$env`, else asked on
+/// the terminal (twice if `confirm`).
+fn passphrase(fd: Option<&str>, env: &str, prompt: &str, confirm: bool) -> Result<String, String> {
+    let passphrase = if let Some(fd) = fd {
+        read_fd(fd)?
+    } else if let Ok(passphrase) = std::env::var(env) {
+        passphrase
+    } else if std::io::stdin().is_terminal() {
+        let passphrase = read_hidden(prompt)?;
+        if confirm && read_hidden("again: ")? != passphrase {
+            return Err("the passphrases do not match".to_owned());
+        }
+        passphrase
+    } else {
+        return Err(format!(
+            "a passphrase is needed, set ${} or use a passphrase fd",
+            env
+        ));
+    };
+    if passphrase.is_empty() {
+        return Err("empty passphrase".to_owned());
+    }
+    Ok(passphrase)
+}
+
+/// First line of what is written to the file descriptor `fd`.
+#[cfg(unix)]
+fn read_fd(fd: &str) -> Result<String, String> {
+    use std::io::Read;
+    use std::os::unix::io::FromRawFd;
+
+    let fd: i32 = match fd.parse() {
+        Ok(fd) if fd >= 0 => fd,
+        _ => return Err(format!("invalid file descriptor {}", fd)),
+    };
+    // read a copy, so `fd` (which may be stdin or stdout) stays open
+    // SAFETY: dup only reads the fd table
+    let copy = unsafe { libc::dup(fd) };
+    if copy < 0 {
+        let e = std::io::Error::last_os_error();
+        return Err(format!("can not read the passphrase from fd {}: {}", fd, e));
+    }
+    // SAFETY: `copy` is a new fd nothing else owns, closed once read
+    let mut file = unsafe { File::from_raw_fd(copy) };
+    let mut content = String::new();
+    file.read_to_string(&mut content)
+        .map_err(|e| format!("can not read the passphrase from fd {}: {}", fd, e))?;
+    Ok(content.lines().next().unwrap_or_default().to_owned())
+}
+
+#[cfg(not(unix))]
+fn read_fd(_: &str) -> Result<String, String> {
+    Err("passphrase file descriptors are only supported on unix".to_owned())
+}
+
+/// Read a line from the terminal without echoing it.
+fn read_hidden(prompt: &str) -> Result<String, String> {
+    eprint!("{}", prompt);
+    let echo = EchoOff::new();
+    let mut line = String::new();
+    let res = std::io::stdin().read_line(&mut line);
+    drop(echo);
+    eprintln!();
+    res.map_err(|e| format!("can not read the passphrase: {}", e))?;
+    Ok(line.trim_end_matches(['\n', '\r']).to_owned())
+}
+
+/// Turns the terminal echo off until dropped.
+struct EchoOff {
+    #[cfg(unix)]
+    saved: Option<libc::termios>,
+}
+
+impl EchoOff {
+    #[cfg(unix)]
+    fn new() -> Self {
+        let mut term = std::mem::MaybeUninit::<libc::termios>::uninit();
+        // SAFETY: tcgetattr fills `term` when it returns 0, it is not read otherwise
+        let saved = unsafe {
+            if libc::tcgetattr(libc::STDIN_FILENO, term.as_mut_ptr()) != 0 {
+                return Self { saved: None };
+            }
+            term.assume_init()
+        };
+        let mut hidden = saved;
+        hidden.c_lflag &= !libc::ECHO;
+        // SAFETY: `hidden` is a valid termios read from the same fd
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &hidden) };
+        Self { saved: Some(saved) }
+    }
+
+    #[cfg(not(unix))]
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Drop for EchoOff {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Some(ref saved) = self.saved {
+            // SAFETY: restores the termios read in `new`
+            unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, saved) };
+        }
+    }
+}
+
+/// Light modules as full blocks, which reads right on dark terminals.
+fn render_qr(code: &QrCode) -> String {
+    code.render::<unicode::Dense1x2>()
+        .dark_color(unicode::Dense1x2::Light)
+        .light_color(unicode::Dense1x2::Dark)
+        .build()
+}
+
+fn write_qr_png(code: &QrCode, path: &str) -> std::io::Result<()> {
+    const SCALE: usize = 8;
+    const QUIET_ZONE: usize = 4;
+    let width = code.width();
+    let size = (width + 2 * QUIET_ZONE) * SCALE;
+    let colors = code.to_colors();
+    let mut pixels = vec![255u8; size * size];
+    for (i, color) in colors.iter().enumerate() {
+        if *color != Color::Dark {
+            continue;
+        }
+        let (x, y) = (i % width + QUIET_ZONE, i / width + QUIET_ZONE);
+        for row in y * SCALE..(y + 1) * SCALE {
+            pixels[row * size + x * SCALE..row * size + (x + 1) * SCALE].fill(0);
+        }
+    }
+
+    let mut encoder = png::Encoder::new(File::create(path)?, size as u32, size as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&pixels)?;
+    Ok(())
+}
+
+/// Exit codes of `status`.
+const STATUS_NOT_SET: i32 = 2;
+const STATUS_EXPIRED: i32 = 3;
+const STATUS_UNREACHABLE: i32 = 4;
+
+fn status(rt: &Runtime) -> i32 {
+    let info = match rt.account_info() {
+        Ok(info) => info,
+        Err(Error::NotLoggedIn) => {
             println!("Warning: cookies not set!");
+            return STATUS_NOT_SET;
         }
+        Err(Error::AuthExpired) => {
+            println!("Login expired, please set cookies again");
+            return STATUS_EXPIRED;
+        }
+        Err(e @ (Error::Network(_) | Error::RemoteNetwork(_) | Error::HttpStatus { .. })) => {
+            eprintln!("can not reach 115: {}", e);
+            return STATUS_UNREACHABLE;
+        }
+        Err(e) => {
+            eprintln!("status check failed, {}", e);
+            return 1;
+        }
+    };
+    println!(
+        "You are in as {} (user id {}, profile {})",
+        info.user_name,
+        info.user_id,
+        rt.profile_name()
+    );
+    match (info.vip, info.vip_expire) {
+        (true, Some(t)) => println!("vip: yes, until {}", format_date(t)),
+        (true, None) => println!("vip: yes"),
+        (false, _) => println!("vip: no"),
+    }
+    println!(
+        "space: {} used of {}",
+        format_size(info.space_used),
+        format_size(info.space_total)
+    );
+    if info.user_key_valid {
+        println!("user key: valid");
+    } else {
+        println!("user key: outdated, run set-cookies again to refresh it");
     }
     0
 }
 
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// Draws how far a hash run is over one stderr line, when it is a terminal.
+fn show_progress(progress: &Progress) {
+    if !std::io::stderr().is_terminal() {
+        return;
+    }
+    eprint!(
+        "\r\x1b[K{}/{} files, {} of {}, {}/s",
+        progress.files,
+        progress.total_files,
+        format_size(progress.bytes),
+        format_size(progress.total_bytes),
+        format_size(progress.throughput() as u64)
+    );
+}
+
+/// Clears the progress line, for what is printed next.
+fn end_progress() {
+    if std::io::stderr().is_terminal() {
+        eprint!("\r\x1b[K");
+    }
+}
+
+/// `YYYY-MM-DD` (UTC) of a unix time.
+fn format_date(unix: i64) -> String {
+    // days to civil date, from Howard Hinnant's date algorithms
+    let z = unix.div_euclid(86400) + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+const LOG_ENV: &str = "RS115_LOG";
+
+/// Log to stderr at the level of `-v`, else as `$RS115_LOG` filters (like
+/// `RUST_LOG`), else warnings only.
+fn init_logging(matches: &ArgMatches) {
+    use tracing_subscriber::EnvFilter;
+    let filter = match (matches.occurrences_of("verbose"), std::env::var(LOG_ENV)) {
+        (0, Ok(directives)) if !directives.is_empty() => EnvFilter::try_new(&directives)
+            .unwrap_or_else(|e| {
+                eprintln!("ignoring {}: {}", LOG_ENV, e);
+                EnvFilter::new("warn")
+            }),
+        (0, _) => EnvFilter::new("warn"),
+        (1, _) => EnvFilter::new("warn,rs115=debug"),
+        _ => EnvFilter::new("warn,rs115=trace"),
+    };
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal())
+        .init();
+}
+
+const CONFIG_FILE: &str = "config.json";
+const PROXY_ENV: &str = "RS115_PROXY";
+const PROXY_USER_ENV: &str = "RS115_PROXY_USER";
+const NO_PROXY_ENV: &str = "RS115_NO_PROXY";
+const CA_CERT_ENV: &str = "RS115_CA_CERT";
+const BIND_ENV: &str = "RS115_BIND";
+const TIMEOUT_ENV: &str = "RS115_TIMEOUT";
+const CONNECT_TIMEOUT_ENV: &str = "RS115_CONNECT_TIMEOUT";
+
+/// `config.json` of the config dir, every key optional. The env vars and
+/// flags win over it.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    proxy: Option<String>,
+    proxy_user: Option<String>,
+    no_proxy: Option<String>,
+    #[serde(default)]
+    ca_certs: Vec<PathBuf>,
+    bind: Option<String>,
+    /// ms
+    timeout: Option<u64>,
+    /// ms
+    connect_timeout: Option<u64>,
+}
+
+fn read_config_file() -> Result<ConfigFile, String> {
+    let path = match config_dir() {
+        Ok(dir) => dir.join(CONFIG_FILE),
+        Err(_) => return Ok(ConfigFile::default()),
+    };
+    match std::fs::read_to_string(&path) {
+        Ok(s) => serde_json::from_str(&s).map_err(|e| format!("invalid {}: {}", path.display(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(ConfigFile::default()),
+        Err(e) => Err(format!("can not read {}: {}", path.display(), e)),
+    }
+}
+
+/// The flag `name`, else the env var `env`, else the config file value.
+fn setting(matches: &ArgMatches, name: &str, env: &str, file: Option<String>) -> Option<String> {
+    matches
+        .value_of(name)
+        .map(str::to_owned)
+        .or_else(|| std::env::var(env).ok().filter(|v| !v.is_empty()))
+        .or(file)
+}
+
+fn parse_network(matches: &ArgMatches, config: &mut SessionConfig) -> Result<(), String> {
+    let file = read_config_file()?;
+    let network = &mut config.network;
+    network.proxy = setting(matches, "proxy", PROXY_ENV, file.proxy);
+    network.proxy_user = setting(matches, "proxy_user", PROXY_USER_ENV, file.proxy_user);
+    network.no_proxy = setting(matches, "no_proxy", NO_PROXY_ENV, file.no_proxy);
+    network.ca_certs = match (
+        matches.values_of_os("ca_cert"),
+        std::env::var_os(CA_CERT_ENV),
+    ) {
+        (Some(paths), _) => paths.map(PathBuf::from).collect(),
+        (None, Some(paths)) if !paths.is_empty() => std::env::split_paths(&paths).collect(),
+        _ => file.ca_certs,
+    };
+    if let Some(bind) = setting(matches, "bind", BIND_ENV, file.bind) {
+        let addr = bind.parse().map_err(|_| "bind must be an ip address")?;
+        network.bind = Some(addr);
+    }
+
+    let ms = |name: &str, env: &str, file: Option<u64>| match setting(
+        matches,
+        name,
+        env,
+        file.map(|t| t.to_string()),
+    ) {
+        None => Ok(None),
+        Some(t) => match t.parse::<u64>() {
+            Ok(t) if t > 0 => Ok(Some(Duration::from_millis(t))),
+            _ => Err(format!(
+                "{} must be a positive number",
+                name.replace('_', "-")
+            )),
+        },
+    };
+    config.timeout = ms("timeout", TIMEOUT_ENV, file.timeout)?;
+    config.connect_timeout = ms("connect_timeout", CONNECT_TIMEOUT_ENV, file.connect_timeout)?;
+    Ok(())
+}
+
+fn parse_config(matches: &ArgMatches) -> Result<SessionConfig, String> {
+    let mut config = SessionConfig {
+        endpoints: parse_endpoints(matches),
+        ..Default::default()
+    };
+    parse_network(matches, &mut config)?;
+    if let Some(name) = matches.value_of("client") {
+        config.client = Some(parse_client(name)?);
+    }
+    if let Some(n) = matches.value_of("max_attempts") {
+        match n.parse::<u32>() {
+            Ok(n) if n > 0 => config.retry.max_attempts = n,
+            _ => return Err("max-attempts must be a positive number".into()),
+        }
+    }
+    if let Some(t) = matches.value_of("retry_delay") {
+        match t.parse::<u64>() {
+            Ok(t) => config.retry.base_delay = Duration::from_millis(t),
+            Err(_) => {
+                return Err("retry-delay must be a positive number of milliseconds or 0".into())
+            }
+        }
+    }
+    let mut limit = config.rate_limit.global.unwrap_or(RateLimit::new(2.0, 5));
+    if let Some(n) = matches.value_of("burst") {
+        match n.parse::<u32>() {
+            Ok(n) if n > 0 => limit.burst = n,
+            _ => return Err("burst must be a positive number".into()),
+        }
+    }
+    match matches.value_of("rate").map(str::parse::<f64>) {
+        None => config.rate_limit.global = Some(limit),
+        Some(Ok(0.0)) => config.rate_limit.global = None,
+        Some(Ok(n)) if n > 0.0 && n.is_finite() => {
+            limit.requests_per_second = n;
+            config.rate_limit.global = Some(limit);
+        }
+        Some(_) => return Err("rate must be a positive number or 0".into()),
+    }
+    Ok(config)
+}
+
+fn parse_hashing(matches: &ArgMatches) -> Result<HashConfig, String> {
+    let mut config = HashConfig::default();
+    if let Some(n) = matches.value_of("threads") {
+        match n.parse::<usize>() {
+            Ok(n) if n > 0 => config.threads = n,
+            _ => return Err("threads must be a positive number".into()),
+        }
+    }
+    if let Some(n) = matches.value_of("buffer_size") {
+        match n.parse::<usize>().ok().and_then(|n| n.checked_mul(1024)) {
+            Some(n) if n > 0 => config.buffer_size = n,
+            _ => return Err("buffer-size must be a positive number of KiB".into()),
+        }
+    }
+    Ok(config)
+}
+
+fn parse_endpoints(matches: &ArgMatches) -> Endpoints {
+    let mut endpoints = Endpoints::from_env();
+    if let Some(url) = matches.value_of("proapi_url") {
+        endpoints.set_proapi(url);
+    }
+    if let Some(url) = matches.value_of("webapi_url") {
+        endpoints.set_webapi(url);
+    }
+    if let Some(url) = matches.value_of("uplb_url") {
+        endpoints.set_uplb(url);
+    }
+    if let Some(url) = matches.value_of("my_url") {
+        endpoints.set_my(url);
+    }
+    if let Some(url) = matches.value_of("qrcode_url") {
+        endpoints.set_qrcode(url);
+    }
+    if let Some(url) = matches.value_of("passport_url") {
+        endpoints.set_passport(url);
+    }
+    endpoints
+}
+
 fn parse_optional_path(matches: &ArgMatches, name: &str, to: &mut Option<File>) -> i32 {
     if let Some(path) = matches.value_of(name) {
         let fpath = Path::new(path);
@@ -118,3 +1106,29 @@ fn main() {
     let exit_code = app();
     std::process::exit(exit_code);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_test() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(1709251199), "2024-02-29");
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(115 * 1024 * 1024 * 1024), "115.0 GiB");
+    }
+
+    #[test]
+    fn qr_png_test() {
+        let code = QrCode::new("https://115.com/scan/dg-qr1").unwrap();
+        let path = std::env::temp_dir().join(format!("rs115-qr-{}.png", std::process::id()));
+        write_qr_png(&code, path.to_str().unwrap()).unwrap();
+
+        let decoder = png::Decoder::new(File::open(&path).unwrap());
+        let info = decoder.read_info().unwrap().info().clone();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(info.width, (code.width() as u32 + 8) * 8);
+        assert_eq!(info.color_type, png::ColorType::Grayscale);
+    }
+}
@@ -1,34 +1,134 @@
 use std::str::FromStr;
 
+use crate::api::RapidUploadRequest;
+use crate::checkers::is_valid_sha1_hex;
+use crate::error::Error;
 use serde::{Deserialize, Serialize};
 use std::fs::File;
 use std::io::BufReader;
 
-type Sha1Link = String;
+const LINK_PREFIX: &str = "115://";
 
-#[derive(Debug, Serialize, Deserialize)]
-pub(crate) struct Sha1JsonFolderEntity {
-    dir_name: String,
-    files: Vec<Sha1Link>,
-    dirs: Vec<Self>,
+/// A parsed `115://name|size|sha1|blocksha1` link.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sha1Link {
+    pub name: String,
+    pub size: u64,
+    /// sha1 of the whole file, upper case
+    pub sha1: String,
+    /// sha1 of the first 128KiB, upper case
+    pub block_sha1: String,
+}
+
+impl Sha1Link {
+    /// The rapid upload of this file into folder `cid`.
+    pub fn to_request(&self, cid: u64) -> RapidUploadRequest {
+        RapidUploadRequest {
+            filename: self.name.clone(),
+            file_size: self.size,
+            sha1: self.sha1.clone(),
+            block_sha1: self.block_sha1.clone(),
+            cid,
+        }
+    }
+}
+
+impl FromStr for Sha1Link {
+    type Err = Parse115SHA1Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s
+            .trim()
+            .strip_prefix(LINK_PREFIX)
+            .ok_or(Parse115SHA1Error())?;
+        let res: Vec<&str> = s.split('|').collect();
+        if res.len() < 4 || res[0].is_empty() {
+            return Err(Parse115SHA1Error());
+        }
+        let size = res[1].parse().map_err(|_| Parse115SHA1Error())?;
+        if !is_valid_sha1_hex(res[2]) || !is_valid_sha1_hex(res[3]) {
+            return Err(Parse115SHA1Error());
+        }
+        Ok(Self {
+            name: res[0].to_owned(),
+            size,
+            sha1: res[2].to_uppercase(),
+            block_sha1: res[3].to_uppercase(),
+        })
+    }
+}
+
+impl std::fmt::Display for Sha1Link {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}{}|{}|{}|{}",
+            LINK_PREFIX, self.name, self.size, self.sha1, self.block_sha1
+        )
+    }
+}
+
+/// A folder of `115://` links, as found in shared json trees.
+///
+/// `files` keeps the raw lines, parse them with [`Sha1Link::from_str`].
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sha1JsonFolderEntity {
+    pub dir_name: String,
+    pub files: Vec<String>,
+    pub dirs: Vec<Self>,
 }
 
 impl FromStr for Sha1JsonFolderEntity {
-    type Err = Box<dyn std::error::Error>;
+    type Err = Error;
 
+    /// Read the json tree from the file at path `s`.
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let file = File::open(s)?;
         let reader = BufReader::new(file);
-        Ok(serde_json::from_reader::<_, Sha1JsonFolderEntity>(reader)?)
+        serde_json::from_reader::<_, Sha1JsonFolderEntity>(reader)
+            .map_err(|e| Error::decode(format!("{} in {}", e, s), ""))
     }
 }
 
 #[derive(Debug)]
-struct Parse115SHA1Error();
+pub struct Parse115SHA1Error();
 impl std::error::Error for Parse115SHA1Error {}
 
 impl std::fmt::Display for Parse115SHA1Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "invalid file")
+        write!(f, "invalid 115 sha1 link")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sha1_link_round_trip_test() {
+        let line = "115://[座头鲸 Humpback Whales 2015][3D+2D][无中字][18.52GB].iso|19880869888|702c4e22be8f3d856c496178c488e86b606d9912|13F48115A678499823003C8331E9C0AD0243F089";
+        let link: Sha1Link = line.parse().unwrap();
+        assert_eq!(link.size, 19880869888);
+        assert_eq!(link.sha1, "702C4E22BE8F3D856C496178C488E86B606D9912");
+        assert_eq!(
+            link.to_string(),
+            line.replace("702c4e22be8f3d856c496178c488e86b606d9912", &link.sha1)
+        );
+    }
+
+    #[test]
+    fn invalid_sha1_link_test() {
+        assert!("".parse::<Sha1Link>().is_err());
+        assert!("name|5|702C4E22BE8F3D856C496178C488E86B606D9912|13F48115A678499823003C8331E9C0AD0243F089"
+            .parse::<Sha1Link>()
+            .is_err());
+        assert!("115://name|five|702C4E22BE8F3D856C496178C488E86B606D9912|13F48115A678499823003C8331E9C0AD0243F089"
+            .parse::<Sha1Link>()
+            .is_err());
+        assert!(
+            "115://name|5|702C4E22BE8F3D856C|13F48115A678499823003C8331E9C0AD0243F089"
+                .parse::<Sha1Link>()
+                .is_err()
+        );
     }
 }
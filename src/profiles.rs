@@ -0,0 +1,215 @@
+//! The cookie cache: named account profiles plus the default one.
+//!
+//! The cache is versioned json. Version 1 was a single serialized session
+//! (`{"cookies": .., "user_id": .., "user_key": ..}`) and is read as the
+//! profile `default`; it is written back in the current version on the next
+//! change.
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::api::{SessionBuilder, SessionConfig};
+use crate::client::ClientIdentity;
+use crate::error::{Error, Result};
+use crate::redact;
+
+/// Version written by this build.
+pub const CACHE_VERSION: u32 = 2;
+/// Profile used when none is named and the cache has no default.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// What is cached of one logged in account.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Profile {
+    pub cookies: String,
+    pub user_id: Option<String>,
+    pub user_key: Option<String>,
+    /// client chosen for this account, `None` for the default one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client: Option<ClientIdentity>,
+}
+
+/// Cookies and user keys masked, see [`crate::redact`].
+impl std::fmt::Debug for Profile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Profile")
+            .field("cookies", &redact::cookies(&self.cookies))
+            .field("user_id", &redact::option(&self.user_id))
+            .field("user_key", &redact::option(&self.user_key))
+            .field("client", &self.client)
+            .finish()
+    }
+}
+
+impl Profile {
+    /// A session builder for this account, with the cached user key if any.
+    pub fn builder(&self) -> SessionBuilder {
+        self.builder_with(SessionConfig::default())
+    }
+
+    /// Like [`Profile::builder`] with `config`, whose client wins over the
+    /// profile's.
+    pub fn builder_with(&self, mut config: SessionConfig) -> SessionBuilder {
+        if config.client.is_none() {
+            config.client = self.client.clone();
+        }
+        let builder = SessionBuilder::new(self.cookies.clone()).config(config);
+        match (&self.user_id, &self.user_key) {
+            (Some(id), Some(key)) => builder.user_key(id.clone(), key.clone()),
+            _ => builder,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProfileCache {
+    pub version: u32,
+    /// profile used when none is named
+    pub default: Option<String>,
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+impl Default for ProfileCache {
+    fn default() -> Self {
+        Self {
+            version: CACHE_VERSION,
+            default: None,
+            profiles: BTreeMap::new(),
+        }
+    }
+}
+
+impl ProfileCache {
+    /// Read a cache of any known version.
+    pub fn from_json(s: &str) -> Result<Self> {
+        let value: serde_json::Value = serde_json::from_str(s).map_err(|e| Error::decode(e, s))?;
+        match value.get("version").and_then(|v| v.as_u64()) {
+            Some(v) if v == u64::from(CACHE_VERSION) => {
+                serde_json::from_value(value).map_err(|e| Error::decode(e, s))
+            }
+            Some(v) => Err(Error::decode(
+                format!("unsupported cache version {}", v),
+                "",
+            )),
+            None => {
+                let profile: Profile =
+                    serde_json::from_value(value).map_err(|e| Error::decode(e, s))?;
+                let mut cache = Self::default();
+                cache.insert(DEFAULT_PROFILE, profile)?;
+                Ok(cache)
+            }
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("profile cache is plain json")
+    }
+
+    /// `name`, or the default profile when `None`.
+    pub fn resolve<'a>(&'a self, name: Option<&'a str>) -> &'a str {
+        name.or(self.default.as_deref()).unwrap_or(DEFAULT_PROFILE)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Profile> {
+        self.profiles.get(name)
+    }
+
+    /// Add or replace `name`; the first profile becomes the default.
+    pub fn insert(&mut self, name: &str, profile: Profile) -> Result<()> {
+        check_profile_name(name)?;
+        self.profiles.insert(name.to_owned(), profile);
+        if self.default.is_none() {
+            self.default = Some(name.to_owned());
+        }
+        Ok(())
+    }
+
+    pub fn set_default(&mut self, name: &str) -> Result<()> {
+        if !self.profiles.contains_key(name) {
+            return Err(Error::UnknownProfile(name.to_owned()));
+        }
+        self.default = Some(name.to_owned());
+        Ok(())
+    }
+
+    /// Remove `name`; removing the default makes the first remaining one
+    /// the default.
+    pub fn remove(&mut self, name: &str) -> Result<Profile> {
+        let profile = self
+            .profiles
+            .remove(name)
+            .ok_or_else(|| Error::UnknownProfile(name.to_owned()))?;
+        if self.default.as_deref() == Some(name) {
+            self.default = self.profiles.keys().next().cloned();
+        }
+        Ok(profile)
+    }
+}
+
+/// Names are used on the command line: keep them to `[A-Za-z0-9_.-]`.
+pub(crate) fn check_profile_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'_' | b'.' | b'-'));
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::InvalidProfileName(name.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(cookies: &str) -> Profile {
+        Profile {
+            cookies: cookies.to_owned(),
+            user_id: None,
+            user_key: None,
+            client: None,
+        }
+    }
+
+    #[test]
+    fn migrate_single_session_cache_test() {
+        let v1 = r#"{"cookies":"UID=1; CID=2; SEID=3","user_id":"1","user_key":"key"}"#;
+        let cache = ProfileCache::from_json(v1).unwrap();
+        assert_eq!(cache.version, CACHE_VERSION);
+        assert_eq!(cache.resolve(None), DEFAULT_PROFILE);
+        let default = cache.get(DEFAULT_PROFILE).unwrap();
+        assert_eq!(default.user_key.as_deref(), Some("key"));
+
+        // round trips in the current version
+        assert_eq!(ProfileCache::from_json(&cache.to_json()).unwrap(), cache);
+        assert!(matches!(
+            ProfileCache::from_json(r#"{"version":99,"profiles":{}}"#),
+            Err(Error::Decode { .. })
+        ));
+    }
+
+    #[test]
+    fn profiles_test() {
+        let mut cache = ProfileCache::default();
+        cache.insert("work", profile("a")).unwrap();
+        cache.insert("home", profile("b")).unwrap();
+        assert_eq!(cache.resolve(None), "work");
+        assert_eq!(cache.resolve(Some("home")), "home");
+
+        cache.set_default("home").unwrap();
+        assert_eq!(cache.resolve(None), "home");
+        assert!(matches!(
+            cache.set_default("nope"),
+            Err(Error::UnknownProfile(_))
+        ));
+        assert!(matches!(
+            cache.insert("a b", profile("c")),
+            Err(Error::InvalidProfileName(_))
+        ));
+
+        cache.remove("home").unwrap();
+        assert_eq!(cache.default.as_deref(), Some("work"));
+        cache.remove("work").unwrap();
+        assert_eq!(cache.resolve(None), DEFAULT_PROFILE);
+    }
+}
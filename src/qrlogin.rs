@@ -0,0 +1,97 @@
+//! Logging in by scanning a qr code with the 115 app instead of pasting cookies.
+//!
+//! [`QrLogin::start`] asks qrcodeapi for a token whose `qrcode` text is shown
+//! to the user, [`QrLogin::wait`] polls the scan status until the login is
+//! confirmed on the phone and trades the token for cookies at passportapi.
+use reqwest::blocking::Client;
+use std::time::Duration;
+
+use crate::api::*;
+use crate::endpoints::Endpoints;
+use crate::error::{Error, Result};
+use crate::network::configure;
+use crate::upload::{parse_json, send};
+
+#[derive(Debug)]
+pub struct QrLogin {
+    client: Client,
+    ua: String,
+    endpoints: Endpoints,
+    token: QrToken,
+}
+
+impl QrLogin {
+    /// Ask 115 for a new login qr code.
+    pub fn start(config: &SessionConfig) -> Result<Self> {
+        let ua = config.user_agent();
+        let client = configure(Client::builder(), config)?.build()?;
+        let request = client.get(config.endpoints.qr_token_url());
+        let res = send(&client, request)?;
+        let token = check_qr_token(parse_json(res)?)?;
+
+        Ok(Self {
+            client,
+            ua,
+            endpoints: config.endpoints.clone(),
+            token,
+        })
+    }
+
+    pub fn token(&self) -> &QrToken {
+        &self.token
+    }
+
+    /// The text to show as a qr code.
+    pub fn qr_text(&self) -> &str {
+        &self.token.qrcode
+    }
+
+    /// Ask once how far the scan got.
+    pub fn poll(&self) -> Result<QrStatus> {
+        let request = self
+            .client
+            .get(self.endpoints.qr_status_url())
+            .query(&qr_status_query(&self.token))
+            .header("User-Agent", &self.ua);
+        let res = send(&self.client, request)?;
+
+        check_qr_status(parse_json(res)?)
+    }
+
+    /// Trade the confirmed token for the login cookies.
+    pub fn cookies(&self) -> Result<String> {
+        let request = self
+            .client
+            .post(self.endpoints.qr_login_url())
+            .form(&qr_login_form(&self.token))
+            .header("User-Agent", &self.ua);
+        let res = send(&self.client, request)?;
+
+        check_qr_login(parse_json(res)?)
+    }
+
+    /// Poll every `interval` until the login is confirmed and return the
+    /// cookies, calling `on_status` whenever the status changes.
+    ///
+    /// 115 holds status requests open until something happens, so timeouts
+    /// just mean "still waiting".
+    pub fn wait(&self, interval: Duration, mut on_status: impl FnMut(QrStatus)) -> Result<String> {
+        let mut last = None;
+        loop {
+            let status = match self.poll() {
+                Ok(status) => status,
+                Err(Error::Network(e)) if e.is_timeout() => QrStatus::Waiting,
+                Err(e) => return Err(e),
+            };
+            if last != Some(status) {
+                on_status(status);
+                last = Some(status);
+            }
+            match status {
+                QrStatus::Confirmed => return self.cookies(),
+                QrStatus::Expired | QrStatus::Canceled => return Err(Error::QrLogin(status)),
+                QrStatus::Waiting | QrStatus::Scanned => std::thread::sleep(interval),
+            }
+        }
+    }
+}
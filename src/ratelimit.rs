@@ -0,0 +1,299 @@
+//! Token bucket rate limiting shared by every api call of a session.
+//!
+//! Callers reserve a token and get back how long to wait before sending,
+//! so the same limiter serves the blocking (`thread::sleep`) and async
+//! (`tokio::time::sleep`) sessions and any number of concurrent workers.
+//!
+//! The limiter also adapts to 115: a rate-limit answer pauses every caller
+//! and halves the rate, each success then brings the rate back a bit.
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The 115 api calls a session makes, for per endpoint limits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ApiCall {
+    /// `app/uploadinfo`
+    UserInfo,
+    /// `files/add`
+    CreateDir,
+    /// `rb/delete`
+    Delete,
+    /// `files/getid`
+    FolderId,
+    /// `files`, the listing of a folder
+    List,
+    /// `initupload.php`
+    InitUpload,
+    /// my.115.com `?ct=ajax&ac=nav`
+    Nav,
+    /// `files/index_info`
+    SpaceInfo,
+}
+
+impl ApiCall {
+    /// The endpoint, as in the doc of each call.
+    pub fn endpoint(self) -> &'static str {
+        match self {
+            ApiCall::UserInfo => "app/uploadinfo",
+            ApiCall::CreateDir => "files/add",
+            ApiCall::Delete => "rb/delete",
+            ApiCall::FolderId => "files/getid",
+            ApiCall::List => "files",
+            ApiCall::InitUpload => "initupload.php",
+            ApiCall::Nav => "nav",
+            ApiCall::SpaceInfo => "files/index_info",
+        }
+    }
+}
+
+/// `requests_per_second` on average, with bursts of up to `burst` requests.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub requests_per_second: f64,
+    pub burst: u32,
+}
+
+impl RateLimit {
+    pub fn new(requests_per_second: f64, burst: u32) -> Self {
+        Self {
+            requests_per_second,
+            burst,
+        }
+    }
+
+    /// One request every `interval`, no bursts.
+    pub fn every(interval: Duration) -> Self {
+        Self::new(1.0 / interval.as_secs_f64().max(f64::EPSILON), 1)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimitConfig {
+    /// budget of all calls together, `None` for unlimited
+    pub global: Option<RateLimit>,
+    /// extra budget of single calls, on top of `global`
+    pub per_call: BTreeMap<ApiCall, RateLimit>,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            global: Some(RateLimit::new(2.0, 5)),
+            per_call: BTreeMap::new(),
+        }
+    }
+}
+
+impl RateLimitConfig {
+    pub fn unlimited() -> Self {
+        Self {
+            global: None,
+            per_call: BTreeMap::new(),
+        }
+    }
+}
+
+/// Lowest fraction of the configured rate throttling goes down to.
+const MIN_FACTOR: f64 = 1.0 / 16.0;
+/// How much of the configured rate every success gives back.
+const RECOVER_STEP: f64 = 1.0 / 32.0;
+
+#[derive(Debug)]
+struct Bucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl Bucket {
+    fn new(limit: RateLimit) -> Self {
+        let capacity = f64::from(limit.burst.max(1));
+        Self {
+            rate: limit.requests_per_second.max(f64::EPSILON),
+            capacity,
+            tokens: capacity,
+            last: Instant::now(),
+        }
+    }
+
+    /// Take a token, possibly borrowing from the future; returns the wait
+    /// until that token is actually available.
+    /// The bucket refills at `factor` times its rate.
+    fn reserve(&mut self, now: Instant, factor: f64) -> Duration {
+        let rate = self.rate * factor;
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(self.capacity);
+        self.last = now;
+        self.tokens -= 1.0;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / rate)
+        }
+    }
+}
+
+/// Token buckets for the global and per call budgets of a [`RateLimitConfig`].
+#[derive(Debug)]
+pub struct RateLimiter {
+    global: Option<Mutex<Bucket>>,
+    per_call: BTreeMap<ApiCall, Mutex<Bucket>>,
+    throttle: Mutex<Throttle>,
+}
+
+#[derive(Debug)]
+struct Throttle {
+    /// fraction of the configured rates currently allowed
+    factor: f64,
+    paused_until: Option<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        Self {
+            global: config.global.map(|l| Mutex::new(Bucket::new(l))),
+            per_call: config
+                .per_call
+                .iter()
+                .map(|(call, l)| (*call, Mutex::new(Bucket::new(*l))))
+                .collect(),
+            throttle: Mutex::new(Throttle {
+                factor: 1.0,
+                paused_until: None,
+            }),
+        }
+    }
+
+    /// Reserve one `call` and return how long to wait before sending it.
+    pub fn reserve(&self, call: ApiCall) -> Duration {
+        let now = Instant::now();
+        let (factor, pause) = {
+            let throttle = lock(&self.throttle);
+            let pause = throttle
+                .paused_until
+                .map(|t| t.saturating_duration_since(now))
+                .unwrap_or_default();
+            (throttle.factor, pause)
+        };
+        let global = self
+            .global
+            .as_ref()
+            .map(|b| lock(b).reserve(now, factor))
+            .unwrap_or_default();
+        let single = self
+            .per_call
+            .get(&call)
+            .map(|b| lock(b).reserve(now, factor))
+            .unwrap_or_default();
+        global.max(single).max(pause)
+    }
+
+    /// Block the current thread until `call` may be sent.
+    pub fn acquire(&self, call: ApiCall) {
+        let wait = self.reserve(call);
+        if !wait.is_zero() {
+            std::thread::sleep(wait);
+        }
+    }
+
+    /// 115 said we are too fast: hold every call for `pause` and halve the
+    /// rate, down to 1/16 of the configured one.
+    pub fn slow_down(&self, pause: Duration) {
+        let mut throttle = lock(&self.throttle);
+        throttle.factor = (throttle.factor / 2.0).max(MIN_FACTOR);
+        tracing::debug!(
+            factor = throttle.factor,
+            pause_ms = pause.as_millis() as u64,
+            "rate limited, slowing down"
+        );
+        let until = Instant::now() + pause;
+        throttle.paused_until = Some(throttle.paused_until.map_or(until, |t| t.max(until)));
+    }
+
+    /// A call went through, give back part of the rate taken by `slow_down`.
+    pub fn recover(&self) {
+        let mut throttle = lock(&self.throttle);
+        throttle.factor = (throttle.factor + RECOVER_STEP).min(1.0);
+    }
+
+    /// Fraction of the configured rate currently allowed, `1.0` unless throttled.
+    pub fn throttle_factor(&self) -> f64 {
+        lock(&self.throttle).factor
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new(&RateLimitConfig::default())
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_burst_then_rate_test() {
+        let start = Instant::now();
+        let mut bucket = Bucket::new(RateLimit::new(10.0, 3));
+        bucket.last = start;
+        for _ in 0..3 {
+            assert_eq!(bucket.reserve(start, 1.0), Duration::ZERO);
+        }
+        // the 4th and 5th requests wait for one and two refills
+        assert_eq!(bucket.reserve(start, 1.0).as_millis(), 100);
+        assert_eq!(bucket.reserve(start, 1.0).as_millis(), 200);
+        // a second later the bucket is full again, capped at the burst size
+        let later = start + Duration::from_secs(1);
+        for _ in 0..3 {
+            assert_eq!(bucket.reserve(later, 1.0), Duration::ZERO);
+        }
+        assert!(bucket.reserve(later, 1.0) > Duration::ZERO);
+    }
+
+    #[test]
+    fn per_call_limit_test() {
+        let mut config = RateLimitConfig::unlimited();
+        config.per_call.insert(
+            ApiCall::InitUpload,
+            RateLimit::every(Duration::from_secs(1)),
+        );
+        let limiter = RateLimiter::new(&config);
+
+        assert_eq!(limiter.reserve(ApiCall::InitUpload), Duration::ZERO);
+        assert!(limiter.reserve(ApiCall::InitUpload) > Duration::from_millis(900));
+        // other calls are not limited
+        for _ in 0..100 {
+            assert_eq!(limiter.reserve(ApiCall::CreateDir), Duration::ZERO);
+        }
+    }
+
+    #[test]
+    fn slow_down_and_recover_test() {
+        let limiter = RateLimiter::new(&RateLimitConfig {
+            global: Some(RateLimit::new(100.0, 1)),
+            ..RateLimitConfig::unlimited()
+        });
+        assert_eq!(limiter.reserve(ApiCall::InitUpload), Duration::ZERO);
+
+        limiter.slow_down(Duration::from_secs(2));
+        assert_eq!(limiter.throttle_factor(), 0.5);
+        // paused for everyone, whatever the bucket says
+        assert!(limiter.reserve(ApiCall::CreateDir) > Duration::from_millis(1900));
+
+        for _ in 0..10 {
+            limiter.slow_down(Duration::ZERO);
+        }
+        assert_eq!(limiter.throttle_factor(), MIN_FACTOR);
+        for _ in 0..100 {
+            limiter.recover();
+        }
+        assert_eq!(limiter.throttle_factor(), 1.0);
+    }
+}
@@ -0,0 +1,160 @@
+//! Masking of cookies, user keys, signatures and user ids, so logs and
+//! `Debug` output can be attached to bug reports.
+//!
+//! Only the values are masked: names, keys and the shape of what was sent
+//! stay readable.
+
+/// What a masked value is replaced with.
+pub const MASK: &str = "***";
+
+/// Query, form and json keys whose values are masked.
+const SECRET_KEYS: &[&str] = &[
+    "cookie", "cookies", "userkey", "user_key", "sig", "sign", "user_id", "userid", "uid", "UID",
+    "CID", "SEID",
+];
+
+fn is_secret(key: &str) -> bool {
+    SECRET_KEYS.contains(&key)
+}
+
+/// `MASK` for `Some`, for the `Debug` of optional secrets.
+pub(crate) fn option<T>(value: &Option<T>) -> Option<&'static str> {
+    value.as_ref().map(|_| MASK)
+}
+
+/// A `Cookie` header with every value masked: `UID=***; CID=***; SEID=***`.
+pub fn cookies(header: &str) -> String {
+    header
+        .split(';')
+        .map(|pair| match pair.split_once('=') {
+            Some((name, _)) => format!("{}={}", name.trim(), MASK),
+            None => pair.trim().to_owned(),
+        })
+        .filter(|pair| !pair.is_empty())
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// `url` with the secret values of its query masked.
+pub fn url(url: &str) -> String {
+    match url.split_once('?') {
+        Some((path, query)) => format!("{}?{}", path, form_str(query)),
+        None => url.to_owned(),
+    }
+}
+
+/// A `key=value&...` string with the secret values masked.
+pub fn form_str(form: &str) -> String {
+    form.split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((key, _)) if is_secret(key) => format!("{}={}", key, MASK),
+            _ => pair.to_owned(),
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Form fields with the secret values masked.
+pub fn form<K: AsRef<str>, V: ToString>(pairs: &[(K, V)]) -> Vec<(String, String)> {
+    pairs
+        .iter()
+        .map(|(key, value)| {
+            let key = key.as_ref();
+            let value = if is_secret(key) {
+                MASK.to_owned()
+            } else {
+                value.to_string()
+            };
+            (key.to_owned(), value)
+        })
+        .collect()
+}
+
+/// A json (or json looking) body with the string and number values of the
+/// secret keys masked; what is not json is kept as is.
+pub fn json(body: &str) -> String {
+    let mut out = String::with_capacity(body.len());
+    let mut rest = body;
+    while let Some(start) = rest.find('"') {
+        let Some(len) = rest[start + 1..].find('"') else {
+            break;
+        };
+        let key = &rest[start + 1..start + 1 + len];
+        let after_key = start + len + 2;
+        out.push_str(&rest[..after_key]);
+        rest = &rest[after_key..];
+
+        let trimmed = rest.trim_start();
+        if !is_secret(key) || !trimmed.starts_with(':') {
+            continue;
+        }
+        let value = trimmed[1..].trim_start();
+        let value_len = if let Some(string) = value.strip_prefix('"') {
+            match closing_quote(string) {
+                Some(end) => end + 2,
+                None => value.len(),
+            }
+        } else {
+            value
+                .find(|c: char| matches!(c, ',' | '}' | ']') || c.is_whitespace())
+                .unwrap_or(value.len())
+        };
+        if value_len == 0 || value.starts_with(['{', '[']) || value.starts_with("null") {
+            continue;
+        }
+        let skipped = rest.len() - value.len();
+        out.push_str(&rest[..skipped]);
+        out.push('"');
+        out.push_str(MASK);
+        out.push('"');
+        rest = &value[value_len..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Index of the quote ending a json string that starts right after an
+/// opening quote.
+fn closing_quote(s: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redact_test() {
+        assert_eq!(
+            cookies("UID=1150000_A1_1; CID=abc; SEID=def"),
+            "UID=***; CID=***; SEID=***"
+        );
+        assert_eq!(
+            url("https://uplb.115.com/3.0/initupload.php?isp=0&appid=0&sig=ABCD&format=json"),
+            "https://uplb.115.com/3.0/initupload.php?isp=0&appid=0&sig=***&format=json"
+        );
+        assert_eq!(
+            form(&[("userid", "1150000"), ("filename", "a.mkv")]),
+            [
+                ("userid".to_owned(), MASK.to_owned()),
+                ("filename".to_owned(), "a.mkv".to_owned())
+            ]
+        );
+        assert_eq!(
+            json(r#"{"state":true,"user_id":1150000,"userkey":"k\"ey","data":{"UID":"1"}}"#),
+            r#"{"state":true,"user_id":"***","userkey":"***","data":{"UID":"***"}}"#
+        );
+        assert_eq!(json("<html>not json"), "<html>not json");
+        assert_eq!(json(r#"{"sig": 5, "a":"b""#), r#"{"sig": "***", "a":"b""#);
+        assert_eq!(json(r#"{"sig":null}"#), r#"{"sig":null}"#);
+    }
+}
@@ -0,0 +1,127 @@
+//! When and how long to wait before sending a failed api call again.
+use rand::Rng;
+use std::time::Duration;
+
+use crate::error::{Error, STATUS_RATE_LIMITED};
+
+/// Retry policy applied by `Session` and `AsyncSession` to every api call.
+///
+/// Delays grow as `base_delay * 2^(retry - 1)`, capped by `max_delay`; with
+/// `jitter` each delay is scaled by a random factor in `[0.5, 1.0]` so
+/// concurrent workers do not retry in lockstep.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// attempts per call including the first one, `1` disables retries
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub jitter: bool,
+    /// retry connect errors and timeouts
+    pub retry_network: bool,
+    /// retry http 5xx and 429
+    pub retry_server_error: bool,
+    /// 115 statuscode / errno values meaning "too many requests"
+    pub retry_codes: Vec<i64>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(10),
+            jitter: true,
+            retry_network: true,
+            retry_server_error: true,
+            retry_codes: vec![STATUS_RATE_LIMITED],
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Send every call exactly once.
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_retryable(&self, e: &Error) -> bool {
+        match e {
+            #[cfg(any(feature = "blocking", feature = "async"))]
+            Error::Network(e) => self.retry_network && (e.is_connect() || e.is_timeout()),
+            Error::HttpStatus { status, .. } => {
+                self.retry_server_error && (*status >= 500 || *status == 429)
+            }
+            Error::Api { code, .. } => self.retry_codes.contains(code),
+            _ => false,
+        }
+    }
+
+    /// `e` means 115 wants us to slow down, not that the call is wrong.
+    pub fn is_rate_limited(&self, e: &Error) -> bool {
+        match e {
+            Error::HttpStatus { status, .. } => *status == 429,
+            Error::Api { code, .. } => self.retry_codes.contains(code),
+            _ => false,
+        }
+    }
+
+    /// How long to wait before retry number `retry` (starting at 1).
+    pub fn delay(&self, retry: u32) -> Duration {
+        let exp = retry.saturating_sub(1).min(16);
+        let delay = self.base_delay.saturating_mul(1 << exp).min(self.max_delay);
+        if self.jitter {
+            delay.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+        } else {
+            delay
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_test() {
+        let policy = RetryPolicy {
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(1000),
+            jitter: false,
+            ..Default::default()
+        };
+        let delays: Vec<_> = (1..=6).map(|r| policy.delay(r).as_millis()).collect();
+        assert_eq!(delays, [100, 200, 400, 800, 1000, 1000]);
+
+        let policy = RetryPolicy {
+            jitter: true,
+            ..policy
+        };
+        for r in 1..=6 {
+            let delay = policy.delay(r);
+            assert!(delay <= Duration::from_millis(1000));
+            assert!(delay >= Duration::from_millis(50));
+        }
+    }
+
+    #[test]
+    fn retryable_test() {
+        let policy = RetryPolicy::default();
+        assert!(policy.is_retryable(&Error::http_status(502, "")));
+        assert!(policy.is_retryable(&Error::http_status(429, "")));
+        assert!(!policy.is_retryable(&Error::http_status(404, "")));
+        assert!(policy.is_retryable(&Error::Api {
+            code: STATUS_RATE_LIMITED,
+            message: String::new()
+        }));
+        assert!(policy.is_rate_limited(&Error::http_status(429, "")));
+        assert!(!policy.is_rate_limited(&Error::http_status(502, "")));
+        assert!(!policy.is_retryable(&Error::ForbiddenName));
+        assert!(!policy.is_retryable(&Error::VerificationRequired {
+            message: String::new()
+        }));
+        assert!(!policy.is_retryable(&Error::decode("bad json", "")));
+    }
+}
@@ -0,0 +1,417 @@
+//! Where `Runtime` keeps the cookie cache.
+//!
+//! - [`FileStore`]: `cookies.json` in the user config dir, `$RS115_CONFIG_DIR`
+//!   overrides it. The cache older versions wrote next to the executable is
+//!   read until the first save. Optionally encrypted with a passphrase, see
+//!   [`crate::encryption`]; created readable by the owner only.
+//! - [`EnvStore`]: read only cookies from `$RS115_COOKIES`, for ci and
+//!   containers.
+//! - [`MemoryStore`]: nothing touches the disk, for tests and library users.
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::encryption::{is_sealed, Encryption};
+use crate::error::{Error, Result};
+use crate::profiles::{Profile, ProfileCache, DEFAULT_PROFILE};
+
+pub const CONFIG_DIR_ENV: &str = "RS115_CONFIG_DIR";
+pub const COOKIES_ENV: &str = "RS115_COOKIES";
+const CACHE_FILE: &str = "cookies.json";
+/// Cache file of versions before the store, next to the executable.
+const LEGACY_CACHE_FILE: &str = ".COOKIES_115.cache";
+
+pub trait SessionStore: std::fmt::Debug + Send {
+    /// The stored profiles, empty if nothing was stored yet.
+    fn load(&self) -> Result<ProfileCache>;
+    fn save(&self, cache: &ProfileCache) -> Result<()>;
+    /// Forget every stored profile.
+    fn clear(&self) -> Result<()>;
+}
+
+/// `$RS115_COOKIES` if set, the config dir file otherwise; without a config
+/// dir the file next to the executable older versions used.
+pub fn default_store() -> Box<dyn SessionStore> {
+    if std::env::var_os(COOKIES_ENV).is_some() {
+        return Box::new(EnvStore::new());
+    }
+    Box::new(default_file_store())
+}
+
+/// [`FileStore::default_location`], or the file next to the executable
+/// older versions used when there is no config dir.
+pub fn default_file_store() -> FileStore {
+    FileStore::default_location().unwrap_or_else(|_| {
+        let exe = std::env::current_exe().unwrap_or_default();
+        FileStore::new(exe.with_file_name(LEGACY_CACHE_FILE))
+    })
+}
+
+#[derive(Debug, Clone)]
+pub struct FileStore {
+    path: PathBuf,
+    legacy: Option<PathBuf>,
+    encryption: Option<Encryption>,
+}
+
+impl FileStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            legacy: None,
+            encryption: None,
+        }
+    }
+
+    /// `$RS115_CONFIG_DIR/cookies.json`, else `$XDG_CONFIG_HOME/rs115` or
+    /// `~/.config/rs115` (`%APPDATA%\rs115` on windows).
+    pub fn default_location() -> Result<Self> {
+        let dir = config_dir()?;
+        let legacy = std::env::current_exe()
+            .ok()
+            .map(|exe| exe.with_file_name(LEGACY_CACHE_FILE));
+        Ok(Self {
+            path: dir.join(CACHE_FILE),
+            legacy,
+            encryption: None,
+        })
+    }
+
+    /// Read `path` while the store file does not exist yet; the first save
+    /// deletes it, it holds the cookies in plain text.
+    pub fn legacy(mut self, path: impl Into<PathBuf>) -> Self {
+        self.legacy = Some(path.into());
+        self
+    }
+
+    /// Decrypt with, and encrypt every save with, `encryption`. A plain
+    /// cache is still read, and encrypted by the next save.
+    pub fn encrypt(mut self, encryption: Encryption) -> Self {
+        self.encryption = Some(encryption);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the stored cache is encrypted, `false` if there is none.
+    pub fn is_encrypted(&self) -> Result<bool> {
+        Ok(self.read()?.is_some_and(|s| is_sealed(&s)))
+    }
+
+    /// The store file, or the legacy one while the store file does not exist.
+    fn read(&self) -> Result<Option<String>> {
+        let path = match self.legacy {
+            Some(ref legacy) if !self.path.exists() && legacy.exists() => legacy,
+            _ => &self.path,
+        };
+        match std::fs::read_to_string(path) {
+            Ok(s) => Ok(Some(s)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// `$RS115_CONFIG_DIR`, else `rs115` in `$XDG_CONFIG_HOME`, `~/.config` or
+/// `%APPDATA%` on windows.
+pub fn config_dir() -> Result<PathBuf> {
+    if let Some(dir) = std::env::var_os(CONFIG_DIR_ENV) {
+        return Ok(PathBuf::from(dir));
+    }
+    let home = config_home().ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("no config dir found, set {}", CONFIG_DIR_ENV),
+        )
+    })?;
+    Ok(home.join("rs115"))
+}
+
+fn config_home() -> Option<PathBuf> {
+    if cfg!(windows) {
+        return std::env::var_os("APPDATA").map(PathBuf::from);
+    }
+    match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")),
+    }
+}
+
+impl SessionStore for FileStore {
+    fn load(&self) -> Result<ProfileCache> {
+        let s = match self.read()? {
+            Some(s) => s,
+            None => return Ok(ProfileCache::default()),
+        };
+        if !is_sealed(&s) {
+            return ProfileCache::from_json(&s);
+        }
+        let encryption = self.encryption.as_ref().ok_or(Error::PassphraseRequired)?;
+        let plain = String::from_utf8(encryption.open(&s)?).map_err(|e| Error::decode(e, ""))?;
+        ProfileCache::from_json(&plain)
+    }
+
+    fn save(&self, cache: &ProfileCache) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            create_private_dir(dir)?;
+        }
+        let json = cache.to_json();
+        let content = match self.encryption {
+            Some(ref encryption) => encryption.seal(json.as_bytes()),
+            None => json,
+        };
+        let tmp = self.path.with_extension("tmp");
+        write_private(&tmp, content.as_bytes())?;
+        std::fs::rename(tmp, &self.path)?;
+        // the cache is in the new file now, drop the plain text copy
+        for legacy in self.legacy.iter().filter(|legacy| **legacy != self.path) {
+            match std::fs::remove_file(legacy) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn clear(&self) -> Result<()> {
+        for path in std::iter::once(&self.path).chain(&self.legacy) {
+            if path.exists() {
+                std::fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Create `dir` and its parents, the ones created only accessible by the owner.
+fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(dir)
+}
+
+/// Write `path` readable and writable by the owner only, whatever the umask
+/// or the mode of a file left there before.
+fn write_private(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    }
+    file.write_all(content)?;
+    file.sync_all()
+}
+
+/// Cookies of the profile `default` from an environment variable; nothing
+/// can be saved, the user key is fetched again by every run.
+#[derive(Debug, Clone)]
+pub struct EnvStore {
+    var: String,
+}
+
+impl EnvStore {
+    /// Read `$RS115_COOKIES`.
+    pub fn new() -> Self {
+        Self::with_var(COOKIES_ENV)
+    }
+
+    pub fn with_var(var: &str) -> Self {
+        Self {
+            var: var.to_owned(),
+        }
+    }
+
+    fn read_only(&self) -> Error {
+        std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            format!(
+                "cookies come from ${}, unset it to save or clean them",
+                self.var
+            ),
+        )
+        .into()
+    }
+}
+
+impl Default for EnvStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SessionStore for EnvStore {
+    fn load(&self) -> Result<ProfileCache> {
+        let mut cache = ProfileCache::default();
+        if let Ok(cookies) = std::env::var(&self.var) {
+            let cookies = cookies.parse::<crate::cookies::CookieJar>()?;
+            cache.insert(
+                DEFAULT_PROFILE,
+                Profile {
+                    cookies: cookies.to_string(),
+                    user_id: None,
+                    user_key: None,
+                    client: None,
+                },
+            )?;
+        }
+        Ok(cache)
+    }
+
+    fn save(&self, _: &ProfileCache) -> Result<()> {
+        Err(self.read_only())
+    }
+
+    fn clear(&self) -> Result<()> {
+        Err(self.read_only())
+    }
+}
+
+/// Keeps the cache in memory; clones share it.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    cache: Arc<Mutex<ProfileCache>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_cache(cache: ProfileCache) -> Self {
+        Self {
+            cache: Arc::new(Mutex::new(cache)),
+        }
+    }
+
+    /// A copy of what is stored now.
+    pub fn snapshot(&self) -> ProfileCache {
+        self.lock().clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, ProfileCache> {
+        self.cache.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl SessionStore for MemoryStore {
+    fn load(&self) -> Result<ProfileCache> {
+        Ok(self.snapshot())
+    }
+
+    fn save(&self, cache: &ProfileCache) -> Result<()> {
+        *self.lock() = cache.clone();
+        Ok(())
+    }
+
+    fn clear(&self) -> Result<()> {
+        *self.lock() = ProfileCache::default();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COOKIES: &str = "UID=1_A1_1; CID=c; SEID=s";
+
+    fn profile() -> Profile {
+        Profile {
+            cookies: COOKIES.to_owned(),
+            user_id: Some("1".to_owned()),
+            user_key: Some("key".to_owned()),
+            client: None,
+        }
+    }
+
+    #[test]
+    fn file_store_test() {
+        let dir = std::env::temp_dir().join(format!("rs115-store-{}", std::process::id()));
+        let legacy = dir.join(LEGACY_CACHE_FILE);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(&legacy, serde_json::to_string(&profile()).unwrap()).unwrap();
+
+        let store = FileStore::new(dir.join("nested").join(CACHE_FILE)).legacy(&legacy);
+        let mut cache = store.load().unwrap();
+        assert_eq!(cache.get(DEFAULT_PROFILE), Some(&profile()));
+
+        cache.insert("work", profile()).unwrap();
+        store.save(&cache).unwrap();
+        assert!(!legacy.exists());
+        assert_eq!(store.load().unwrap(), cache);
+
+        store.clear().unwrap();
+        assert!(!legacy.exists() && !store.path().exists());
+        assert_eq!(store.load().unwrap(), ProfileCache::default());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn encrypted_file_store_test() {
+        let dir = std::env::temp_dir().join(format!("rs115-sealed-{}", std::process::id()));
+        let plain = FileStore::new(dir.join(CACHE_FILE));
+        let mut cache = ProfileCache::default();
+        cache.insert("work", profile()).unwrap();
+        plain.save(&cache).unwrap();
+        assert!(!plain.is_encrypted().unwrap());
+
+        // a plain cache is read, then encrypted by the next save
+        let sealed = plain.clone().encrypt(Encryption::new("secret").cost(4));
+        assert_eq!(sealed.load().unwrap(), cache);
+        sealed.save(&cache).unwrap();
+        assert!(plain.is_encrypted().unwrap());
+        assert!(!std::fs::read_to_string(plain.path())
+            .unwrap()
+            .contains(COOKIES));
+        assert_eq!(sealed.load().unwrap(), cache);
+        assert!(matches!(plain.load(), Err(Error::PassphraseRequired)));
+        let wrong = plain.clone().encrypt(Encryption::new("guess").cost(4));
+        assert!(matches!(wrong.load(), Err(Error::Decrypt)));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = |p: &Path| std::fs::metadata(p).unwrap().permissions().mode() & 0o777;
+            assert_eq!(mode(plain.path()), 0o600);
+            assert_eq!(mode(&dir), 0o700);
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn env_store_test() {
+        let var = "RS115_TEST_ENV_STORE_COOKIES";
+        let store = EnvStore::with_var(var);
+        assert!(store.load().unwrap().profiles.is_empty());
+
+        std::env::set_var(var, format!(" {}\n", COOKIES));
+        let cache = store.load().unwrap();
+        assert_eq!(cache.get(DEFAULT_PROFILE).unwrap().cookies, COOKIES);
+        assert!(store.save(&cache).is_err());
+
+        std::env::set_var(var, "UID=1");
+        assert!(matches!(store.load(), Err(Error::Cookies(_))));
+        std::env::remove_var(var);
+    }
+
+    #[test]
+    fn memory_store_is_shared_by_clones_test() {
+        let store = MemoryStore::new();
+        let mut cache = ProfileCache::default();
+        cache.insert("work", profile()).unwrap();
+        store.clone().save(&cache).unwrap();
+        assert_eq!(store.snapshot(), cache);
+        store.clone().clear().unwrap();
+        assert!(store.snapshot().profiles.is_empty());
+    }
+}
@@ -1,73 +1,292 @@
-#![allow(dead_code)]
-use crypto::{digest::Digest, sha1::Sha1};
-use reqwest::blocking::Client;
-use serde::{Deserialize, Serialize};
-use serde_json::Value;
-
-const INFO_URL: &str = r"https://proapi.115.com/app/uploadinfo";
-const CREATE_DIR_URL: &str = "https://webapi.115.com/files/add";
-const DELETE_URL: &str = "https://webapi.115.com/rb/delete";
-const TARGET_PREFIX: &str = r"U_1_";
-const APP_VER: &str = r"29.0.0";
-const USER_AGENT_PREFIX: &str = r"Mozilla/5.0 115disk/";
-const END_STRING: &str = r"000000";
-
-type MayBeError = Result<(), Box<dyn std::error::Error>>;
-
-#[derive(Debug, Serialize, Deserialize)]
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::api::*;
+pub use crate::api::{
+    AccountInfo, CreateDirResponseJson, DeleteResponseJson, NavResponseJson, Other,
+    RapidUploadRequest, SessionBuilder, SessionConfig, SpaceInfoResponseJson, StringOri32,
+    UploadResponseJson, UserInfo,
+};
+use crate::client::ClientIdentity;
+use crate::endpoints::Endpoints;
+use crate::error::{Error, Result};
+use crate::network::configure;
+use crate::profiles::Profile;
+use crate::ratelimit::{ApiCall, RateLimiter};
+use crate::redact;
+use crate::retry::RetryPolicy;
+
+type MayBeError = Result<()>;
+
+impl SessionBuilder {
+    pub fn build(mut self) -> Result<Session> {
+        let limiter = self.take_limiter();
+        let config = self.config;
+        let ua = config.user_agent();
+        let client = configure(Client::builder(), &config)?.build()?;
+
+        Ok(Session {
+            cookies: self.cookies,
+            keys: UserKeys::new(self.keys),
+            client,
+            ua,
+            identity: config.client,
+            endpoints: config.endpoints,
+            retry: config.retry,
+            retries: AtomicU64::new(0),
+            key_refreshes: AtomicU64::new(0),
+            limiter,
+        })
+    }
+}
 
-pub(crate) struct Session {
+/// A logged in 115 account, signs and sends every api call.
+///
+/// Only `cookies`, `user_id` and `user_key` are serialized.
+#[derive(Serialize, Deserialize)]
+pub struct Session {
     pub(crate) cookies: String,
-    pub(crate) user_id: Option<String>, // may need to request very time?
-    pub(crate) user_key: Option<String>,
+    #[serde(flatten)]
+    pub(crate) keys: UserKeys,
 
     #[serde(skip)]
     pub(crate) client: Client,
     #[serde(skip)]
     pub(crate) ua: String,
+    /// `None` for the default client
+    #[serde(skip)]
+    pub(crate) identity: Option<ClientIdentity>,
+    #[serde(skip)]
+    pub(crate) endpoints: Endpoints,
+    #[serde(skip)]
+    pub(crate) retry: RetryPolicy,
+    #[serde(skip)]
+    pub(crate) retries: AtomicU64,
+    #[serde(skip)]
+    pub(crate) key_refreshes: AtomicU64,
+    #[serde(skip)]
+    pub(crate) limiter: Arc<RateLimiter>,
+}
+
+/// Cookies and user keys masked, see [`crate::redact`].
+impl std::fmt::Debug for Session {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Session")
+            .field("cookies", &redact::cookies(&self.cookies))
+            .field("keys", &self.keys)
+            .field("ua", &self.ua)
+            .field("identity", &self.identity)
+            .field("endpoints", &self.endpoints)
+            .field("retry", &self.retry)
+            .field("retries", &self.retries)
+            .field("key_refreshes", &self.key_refreshes)
+            .field("limiter", &self.limiter)
+            .finish_non_exhaustive()
+    }
 }
 
 impl Session {
-    pub(crate) fn new(cookies: String) -> Self {
-        let ua: String = format!("{}{}", USER_AGENT_PREFIX, APP_VER);
-        let client = reqwest::blocking::Client::builder()
-            .user_agent(&ua)
-            .build()
-            .expect("TLS backend should be able to initailize");
-
-        Self {
-            cookies,
-            user_id: None,
-            user_key: None,
-            client,
-            ua,
+    /// Start building a session from login cookies.
+    ///
+    /// ```no_run
+    /// use rs115::{Endpoints, Session};
+    /// use std::time::Duration;
+    ///
+    /// let mut session = Session::builder("UID=...; CID=...; SEID=...")
+    ///     .endpoints(Endpoints::default())
+    ///     .timeout(Duration::from_secs(30))
+    ///     .build()?;
+    /// session.get_key_if_none()?;
+    /// # Ok::<(), rs115::Error>(())
+    /// ```
+    pub fn builder(cookies: impl Into<String>) -> SessionBuilder {
+        SessionBuilder::new(cookies)
+    }
+
+    /// Rebuild a deserialized session (which has no http client) with `config`.
+    pub fn restore(self, config: SessionConfig) -> Result<Session> {
+        let builder = Session::builder(self.cookies).config(config);
+        match self.keys.get() {
+            Some((id, key)) => builder.user_key(id, key).build(),
+            None => builder.build(),
+        }
+    }
+
+    pub fn cookies(&self) -> &str {
+        &self.cookies
+    }
+
+    /// What to cache of this session to restore it later.
+    pub fn profile(&self) -> Profile {
+        Profile {
+            cookies: self.cookies.clone(),
+            user_id: self.keys.user_id(),
+            user_key: self.keys.user_key(),
+            client: self.identity.clone(),
+        }
+    }
+
+    pub fn user_id(&self) -> Option<String> {
+        self.keys.user_id()
+    }
+
+    pub fn user_key(&self) -> Option<String> {
+        self.keys.user_key()
+    }
+
+    /// The client this session presents itself as.
+    pub fn client_identity(&self) -> ClientIdentity {
+        self.identity.clone().unwrap_or_default()
+    }
+
+    pub fn endpoints(&self) -> &Endpoints {
+        &self.endpoints
+    }
+
+    /// The limiter every call of this session goes through.
+    pub fn rate_limiter(&self) -> Arc<RateLimiter> {
+        Arc::clone(&self.limiter)
+    }
+
+    /// Retries done so far by this session, over all calls.
+    pub fn retry_count(&self) -> u64 {
+        self.retries.load(Ordering::Relaxed)
+    }
+
+    /// Times the user key was fetched again after 115 rejected it; save
+    /// [`Session::profile`] when this grows.
+    pub fn key_refreshes(&self) -> u64 {
+        self.key_refreshes.load(Ordering::Relaxed)
+    }
+
+    /// Run `call` until it succeeds, fails with a non retryable error or
+    /// runs out of attempts, sleeping as the retry policy says in between.
+    /// Every attempt waits for the rate limiter first; rate-limit answers
+    /// slow the limiter down, so every user of it backs off, not just us.
+    fn with_retry<T>(&self, api: ApiCall, mut call: impl FnMut() -> Result<T>) -> Result<T> {
+        let _span = tracing::debug_span!("call", endpoint = api.endpoint()).entered();
+        let mut attempt = 1;
+        loop {
+            self.limiter.acquire(api);
+            let started = Instant::now();
+            let res = call();
+            trace_attempt(attempt, started, &res);
+            let e = match res {
+                Ok(v) => {
+                    self.limiter.recover();
+                    return Ok(v);
+                }
+                Err(e) => e,
+            };
+            let delay = self.retry.delay(attempt);
+            let rate_limited = self.retry.is_rate_limited(&e);
+            if rate_limited {
+                self.limiter.slow_down(delay);
+            }
+            if attempt >= self.retry.max_attempts || !self.retry.is_retryable(&e) {
+                return Err(e);
+            }
+            if !rate_limited {
+                std::thread::sleep(delay);
+            }
+            self.retries.fetch_add(1, Ordering::Relaxed);
+            attempt += 1;
         }
     }
 
-    pub(crate) fn get_key_if_none(&mut self) -> MayBeError {
-        if self.user_id.is_none() || self.user_key.is_none() {
+    /// Fetch `user_id`/`user_key` unless already known, required before uploading.
+    pub fn get_key_if_none(&mut self) -> MayBeError {
+        if self.keys.get().is_none() {
             self.get_user_key()?
         }
         Ok(())
     }
 
-    pub(crate) fn get_user_key(&mut self) -> MayBeError {
-        let url = INFO_URL.to_string();
-        let res = self
-            .client
-            .get(url)
-            .header("User-Agent", &self.ua)
-            .header("Cookie", &self.cookies)
-            .send()?;
+    /// Ask `app/uploadinfo` for the `user_id`/`user_key` used to sign uploads.
+    pub fn get_user_key(&mut self) -> MayBeError {
+        let (user_id, user_key) = self.fetch_user_key()?;
+        self.keys.set(user_id, user_key);
 
-        let res: UserInfo = res.json()?;
-        self.user_key = Some(res.userkey);
-        self.user_id = Some(res.user_id.to_string());
+        Ok(())
+    }
 
+    /// Replace a user key 115 rejected.
+    fn refresh_user_key(&self) -> MayBeError {
+        let (user_id, user_key) = self.fetch_user_key()?;
+        self.keys.set(user_id, user_key);
+        self.key_refreshes.fetch_add(1, Ordering::Relaxed);
         Ok(())
     }
 
-    pub(crate) fn upload115_sha1(
+    fn fetch_user_key(&self) -> Result<(String, String)> {
+        self.with_retry(ApiCall::UserInfo, || {
+            let url = self.endpoints.info_url();
+            let request = self
+                .client
+                .get(url)
+                .header("User-Agent", &self.ua)
+                .header("Cookie", &self.cookies);
+            let res = send(&self.client, request)?;
+
+            check_user_info(parse_json(res)?)
+        })
+    }
+
+    /// Ask 115 who the cookies belong to, their vip state and space, and
+    /// whether the cached `user_key` is still valid. Nothing is cached.
+    /// Cookies of another user than the cached one are an expired login.
+    pub fn account_info(&self) -> Result<AccountInfo> {
+        let (user_id, user_key) = self.fetch_user_key()?;
+        let cached_id = &self.keys.user_id().unwrap_or_else(|| user_id.clone());
+        let (user_name, vip, vip_expire) = self.with_retry(ApiCall::Nav, || {
+            let request = self
+                .client
+                .get(self.endpoints.nav_url())
+                .header("User-Agent", &self.ua)
+                .header("Cookie", &self.cookies);
+            let res = send(&self.client, request)?;
+
+            check_nav(parse_json(res)?, cached_id)
+        })?;
+        let (space_total, space_used) = self.with_retry(ApiCall::SpaceInfo, || {
+            let request = self
+                .client
+                .get(self.endpoints.space_info_url())
+                .header("User-Agent", &self.ua)
+                .header("Cookie", &self.cookies);
+            let res = send(&self.client, request)?;
+
+            check_space_info(parse_json(res)?)
+        })?;
+
+        Ok(AccountInfo {
+            user_key_valid: self.keys.get() == Some((user_id.clone(), user_key.clone())),
+            user_id,
+            user_name,
+            vip,
+            vip_expire,
+            space_total,
+            space_used,
+        })
+    }
+
+    /// Rapid-upload `req` by hash, `Ok` only when 115 already had the file (status 2).
+    pub fn rapid_upload(&self, req: &RapidUploadRequest) -> MayBeError {
+        self.upload115_sha1(
+            req.filename.clone(),
+            req.file_size.to_string(),
+            req.sha1.clone(),
+            req.block_sha1.clone(),
+            req.cid,
+        )
+    }
+
+    /// Upload by hash. A stale user key is fetched again and the upload
+    /// retried once with it.
+    pub fn upload115_sha1(
         &self,
         filename: String,
         file_size: String,
@@ -75,248 +294,174 @@ impl Session {
         block_hash: String,
         cid: u64,
     ) -> MayBeError {
-        let pre_id = block_hash;
-        let file_id = total_hash.to_uppercase();
-        let quick_id = &file_id;
-        let target = TARGET_PREFIX.to_owned() + &cid.to_string();
-        let user_id = self.user_id.as_ref().ok_or(UploadError::MissingUserid)?;
-        let user_key = self.user_key.as_ref().ok_or(UploadError::MissingUserkey)?;
-
-        let hash = sha1(format!("{}{}{}{}{}", user_id, file_id, quick_id, target, "0").as_str());
-        let sig_string = user_key.to_owned() + &hash + END_STRING;
-        let sig = sha1(&sig_string);
-
-        let param = [
-            ("preid", pre_id),
-            ("filename", filename),
-            ("quickid", quick_id.into()),
-            ("user_id", user_id.into()),
-            ("app_ver", APP_VER.into()),
-            ("filesize", file_size),
-            ("userid", user_id.into()),
-            ("exif", "".into()),
-            ("target", target),
-            ("fileid", total_hash.to_uppercase()),
-        ];
-
-        let url = format!(
-        "https://uplb.115.com/3.0/initupload.php?isp=0&appid=0&appversion={}&format=json&sig={}",
-        APP_VER, sig
-    );
-
-        let res = self
-            .client
-            .post(url)
-            .header("Content-Type", "application/x-www-form-urlencoded")
-            .header("Cookie", &self.cookies)
-            .header("User-Agent", &self.ua)
-            .form(&param)
-            .send()
-            .map_err(|_| UploadError::RequestError)?;
-
-        let res: UploadResponseJson = res.json().expect("json return will always consistent");
-
-        if res.statuscode == 414 {
-            Err(FileNameForbiddenError().into())
-        } else if res.statuscode != 0 {
-            Err(UploadError::Other(res.statusmsg.js_utf8_decode()?).into())
-        } else {
-            let item = res.other.get("status").unwrap();
-            let err = Err(UploadError::Other(
-                "Not succ: ".to_owned() + &res.statusmsg.js_utf8_decode()?,
+        let upload = || {
+            self.signed_upload(
+                filename.clone(),
+                file_size.clone(),
+                total_hash.clone(),
+                block_hash.clone(),
+                cid,
             )
-            .into());
-
-            if !item.is_number() {
-                return err;
-            }
-            let status = item.as_i64().expect("must be number");
-            if status != 2 {
-                err
-            } else {
-                Ok(())
+        };
+        match upload() {
+            Err(Error::StaleUserKey { .. }) => {
+                tracing::info!("user key rejected, fetching a new one");
+                self.refresh_user_key()?;
+                upload()
             }
+            res => res,
         }
     }
 
-    pub(crate) fn create_folder(
+    fn signed_upload(
         &self,
-        pid: u64,
-        name: String,
-    ) -> Result<u64, Box<dyn std::error::Error>> {
-        let param = [("pid", pid.to_string()), ("cname", name)];
-        let url = CREATE_DIR_URL.to_owned();
-        let res: CreateDirResponseJson = self
-            .client
-            .post(url)
-            .form(&param)
-            .header("User-Agent", &self.ua)
-            .header("Cookie", &self.cookies)
-            .header("Content-Type", "application/x-www-form-urlencoded")
-            .send()?
-            .json()?;
-
-        if !res.state {
-            if let StringOri32::Number(20004) = res.errno {
-                // dir exist in the cloud
-                Err(UploadError::DirExist.into())
-            } else {
-                Err("create dir failed...".into())
-            }
-        } else {
-            Ok(res.cid.expect("should return valid cid").parse()?)
-        }
+        filename: String,
+        file_size: String,
+        total_hash: String,
+        block_hash: String,
+        cid: u64,
+    ) -> MayBeError {
+        let (user_id, user_key) = self.keys.get().ok_or(Error::MissingUserKey)?;
+        let client = self.client_identity();
+        let (url, param) = upload_request(
+            &self.endpoints,
+            &client,
+            &user_id,
+            &user_key,
+            filename,
+            file_size,
+            total_hash,
+            block_hash,
+            cid,
+        );
+
+        self.with_retry(ApiCall::InitUpload, || {
+            let request = self
+                .client
+                .post(&url)
+                .header("Content-Type", "application/x-www-form-urlencoded")
+                .header("Cookie", &self.cookies)
+                .header("User-Agent", &self.ua)
+                .form(&param);
+            let res = send(&self.client, request)?;
+
+            check_upload(parse_json(res)?, &client.app_version)
+        })
     }
 
-    pub(crate) fn delete_one(&self, pid: u64, target: u64) -> MayBeError {
-        let param = [("pid", pid), ("fid[0]", target), ("ignore_warn", 1)];
-
-        let url = DELETE_URL.to_owned();
-        let res: DeleteResponseJson = self
-            .client
-            .post(url)
-            .form(&param)
-            .header("User-Agent", &self.ua)
-            .header("Cookie", &self.cookies)
-            .header("Content-Type", "application/x-www-form-urlencoded")
-            .send()?
-            .json()?;
-
-        if res.state {
-            Ok(())
-        } else {
-            Err(UploadError::DeleteFails.into())
+    /// Create folder `name` under `pid`, returns the new cid. When a retry
+    /// finds the folder there, the attempt before made it and its cid is
+    /// returned.
+    pub fn create_folder(&self, pid: u64, name: String) -> Result<u64> {
+        let param = create_dir_form(pid, name.clone());
+        let url = self.endpoints.create_dir_url();
+        let mut attempts = 0;
+        let res = self.with_retry(ApiCall::CreateDir, || {
+            attempts += 1;
+            let request = self
+                .client
+                .post(&url)
+                .form(&param)
+                .header("User-Agent", &self.ua)
+                .header("Cookie", &self.cookies)
+                .header("Content-Type", "application/x-www-form-urlencoded");
+            let res = send(&self.client, request)?;
+
+            check_create_dir(parse_json(res)?)
+        });
+        match res {
+            // an attempt whose answer was lost may have made the folder
+            Err(Error::DirExist) if attempts > 1 => {
+                self.find_folder(pid, &name)?.ok_or(Error::DirExist)
+            }
+            res => res,
         }
     }
 
-    pub(crate) fn delete_bulk(&self, pid: u64, target_list: Vec<u64>) -> MayBeError {
-        let mut param = [("pid".to_owned(), pid), ("ignore_warn".to_owned(), 1)].to_vec();
-
-        for (i, item) in target_list.into_iter().enumerate() {
-            let key = format!("fid[{}]", i);
-            let value = item;
-            param.push((key, value));
+    /// The cid of the folder at `path`, like `/movies/2024`; `/` is 0.
+    pub fn folder_id(&self, path: &str) -> Result<u64> {
+        let path = path.trim_end_matches('/');
+        if path.is_empty() {
+            return Ok(0);
         }
+        let query = folder_id_query(path);
+        let url = self.endpoints.folder_id_url();
+        self.with_retry(ApiCall::FolderId, || {
+            let request = self
+                .client
+                .get(&url)
+                .query(&query)
+                .header("User-Agent", &self.ua)
+                .header("Cookie", &self.cookies);
+            let res = send(&self.client, request)?;
+
+            check_folder_id(parse_json(res)?, path)
+        })
+    }
 
-        let url = DELETE_URL.to_owned();
-        let res: DeleteResponseJson = self
-            .client
-            .post(url)
-            .form(&param)
-            .header("User-Agent", &self.ua)
-            .header("Cookie", &self.cookies)
-            .header("Content-Type", "application/x-www-form-urlencoded")
-            .send()?
-            .json()?;
-
-        if res.state {
-            Ok(())
-        } else {
-            Err(UploadError::DeleteFails.into())
+    /// The cid of the folder `name` in `pid`, if there is one.
+    pub fn find_folder(&self, pid: u64, name: &str) -> Result<Option<u64>> {
+        let url = self.endpoints.list_url();
+        let mut offset = 0;
+        loop {
+            let query = list_folders_query(pid, offset);
+            let (folders, count) = self.with_retry(ApiCall::List, || {
+                let request = self
+                    .client
+                    .get(&url)
+                    .query(&query)
+                    .header("User-Agent", &self.ua)
+                    .header("Cookie", &self.cookies);
+                let res = send(&self.client, request)?;
+
+                check_list_folders(parse_json(res)?)
+            })?;
+            if let Some(&(cid, _)) = folders.iter().find(|(_, n)| n == name) {
+                return Ok(Some(cid));
+            }
+            offset += folders.len();
+            if folders.is_empty() || offset >= count {
+                return Ok(None);
+            }
         }
     }
-}
-
-trait JsUnicodeEncoded {
-    fn js_utf8_decode(&self) -> Result<String, Box<dyn std::error::Error>>;
-}
 
-use unescape::unescape;
-impl JsUnicodeEncoded for String {
-    fn js_utf8_decode(&self) -> Result<String, Box<dyn std::error::Error>> {
-        unescape(&self).ok_or("js_utf8_decode failed".into())
+    /// Move `target` in folder `pid` to the recycle bin.
+    pub fn delete_one(&self, pid: u64, target: u64) -> MayBeError {
+        self.delete_bulk(pid, vec![target])
     }
-}
 
-type Other = std::collections::BTreeMap<String, Value>;
-#[derive(Deserialize)]
-pub(crate) struct UserInfo {
-    user_id: i32,
-    userkey: String,
-    #[serde(flatten)]
-    other: Other,
-}
-
-#[derive(Deserialize, Debug)]
-pub(crate) struct UploadResponseJson {
-    statuscode: u32,
-    statusmsg: String,
-    #[serde(flatten)]
-    other: Other,
-}
-
-#[derive(Deserialize, Debug)]
-pub(crate) struct CreateDirResponseJson {
-    state: bool,
-    errno: StringOri32,
-    cid: Option<String>,
-    #[serde(flatten)]
-    other: Other,
-}
-
-#[derive(Deserialize, Debug)]
-#[serde(untagged)]
-enum StringOri32 {
-    S(String),
-    Number(i32),
-}
-
-#[derive(Deserialize, Debug)]
-pub(crate) struct DeleteResponseJson {
-    state: bool,
-    #[serde(flatten)]
-    other: Other,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub(crate) struct File {
-    path: String,
-    cid: u64,
-}
-
-impl File {
-    fn test() {}
-}
-
-#[derive(Debug)]
-pub(crate) struct FileNameForbiddenError();
-impl std::fmt::Display for FileNameForbiddenError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "name not allowed by 115, filename may contains word in the censor list"
-        )
+    pub fn delete_bulk(&self, pid: u64, target_list: Vec<u64>) -> MayBeError {
+        let param = delete_form(pid, target_list);
+        let url = self.endpoints.delete_url();
+        self.with_retry(ApiCall::Delete, || {
+            let request = self
+                .client
+                .post(&url)
+                .form(&param)
+                .header("User-Agent", &self.ua)
+                .header("Cookie", &self.cookies)
+                .header("Content-Type", "application/x-www-form-urlencoded");
+            let res = send(&self.client, request)?;
+
+            check_delete(parse_json(res)?)
+        })
     }
 }
-impl std::error::Error for FileNameForbiddenError {}
-
-#[derive(Debug)]
-pub(crate) enum UploadError {
-    MissingUserid,
-    MissingUserkey,
-    RequestError,
-    DeleteFails,
-    DirExist,
-    Other(String),
-}
 
-impl std::fmt::Display for UploadError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            UploadError::MissingUserid => write!(f, "missing userid"),
-            UploadError::MissingUserkey => write!(f, "missing userkey"),
-            UploadError::Other(ctx) => write!(f, "error: {}", ctx),
-            UploadError::RequestError => write!(f, "network request error"),
-            UploadError::DirExist => write!(f, "create folder failed, dir already exist"),
-            UploadError::DeleteFails => write!(f, "delete failed.."),
-        }
-    }
+/// Build and send `request`, tracing it with the secrets masked.
+pub(crate) fn send(client: &Client, request: RequestBuilder) -> Result<Response> {
+    let request = request.build()?;
+    trace_request(
+        request.method().as_str(),
+        request.url().as_str(),
+        request.body().and_then(|body| body.as_bytes()),
+    );
+    Ok(client.execute(request)?)
 }
 
-impl std::error::Error for UploadError {}
-
-fn sha1(content: &str) -> String {
-    let mut hasher = Sha1::new();
-    hasher.input_str(content);
-    hasher.result_str()
+pub(crate) fn parse_json<T: DeserializeOwned>(res: Response) -> Result<T> {
+    let status = res.status().as_u16();
+    let body = res.text()?;
+    parse_body(status, &body)
 }
@@ -0,0 +1,143 @@
+#![cfg(all(unix, feature = "agent"))]
+use mock115::MockServer;
+use rs115::agent::bind;
+use rs115::{
+    Agent, AgentClient, Endpoints, Error, MemoryStore, ProfileCache, Runtime, SessionConfig,
+};
+use std::io::{BufRead, Write};
+
+const COOKIES: &str = "UID=1150000_A1_1650000000; CID=abcdef; SEID=0123456789";
+
+#[test]
+fn agent_makes_the_calls_test() {
+    let server = MockServer::builder()
+        .cookies(COOKIES)
+        .censor(["forbidden"])
+        .all_sha1_known()
+        .start();
+    let config = || SessionConfig {
+        endpoints: Endpoints::all(server.url()),
+        ..Default::default()
+    };
+    let mut cache = ProfileCache::default();
+    let profile = rs115::Session::builder(COOKIES).build().unwrap().profile();
+    cache.insert("work", profile).unwrap();
+
+    let dir = std::env::temp_dir().join(format!("rs115-agent-{}", std::process::id()));
+    let path = dir.join("agent.sock");
+    let listener = bind(&path).unwrap();
+    assert!(bind(&path).is_err());
+    let store = MemoryStore::with_cache(cache);
+    let agent = Agent::new(Box::new(store.clone()), config()).unwrap();
+    let agent = std::thread::spawn(move || agent.serve(listener));
+
+    let client = AgentClient::new(&path);
+    // no secrets leave the agent
+    let profiles = client.profiles().unwrap();
+    assert_eq!(profiles.default.as_deref(), Some("work"));
+    assert!(profiles.get("work").unwrap().cookies.is_empty());
+
+    let mut rt = Runtime::with_agent(config(), None, client.clone()).unwrap();
+    assert!(rt.check_name("github").unwrap());
+    assert!(!rt.check_name("a forbidden name").unwrap());
+    assert_eq!(rt.account_info().unwrap().user_id, "1150000");
+    assert!(matches!(rt.set_cookies(COOKIES), Err(Error::Agent(_))));
+    assert!(server.folders().is_empty());
+
+    // a stale key is fetched again by the agent and saved to its store
+    server.set_user_key("rotated");
+    assert!(rt.check_name("github").unwrap());
+    let work = store.snapshot().profiles["work"].clone();
+    assert_eq!(work.user_key.as_deref(), Some("rotated"));
+
+    // imports go through the agent too, errors keep their variant
+    let link = "115://a.iso|5|702C4E22BE8F3D856C496178C488E86B606D9912|13F48115A678499823003C8331E9C0AD0243F089";
+    let summary = rt.import(link.as_bytes(), "/", None::<Vec<u8>>).unwrap();
+    assert_eq!(summary.imported, 1);
+    // and tell the requests the agent retried
+    server.push_response("/3.0/initupload.php", 502, "bad gateway");
+    let summary = rt.import(link.as_bytes(), "/", None::<Vec<u8>>).unwrap();
+    assert_eq!((summary.already_exists, summary.retries), (1, 1));
+    assert_eq!(client.retry_count(), 1);
+    assert_eq!(
+        rt.import(link.as_bytes(), "0", None::<Vec<u8>>)
+            .unwrap()
+            .already_exists,
+        1
+    );
+    let res = rt.import(link.as_bytes(), "/missing", None::<Vec<u8>>);
+    assert!(matches!(res, Err(Error::FolderNotFound(path)) if path == "/missing"));
+    let tree = rs115::Sha1JsonFolderEntity {
+        dir_name: "tree".to_owned(),
+        files: vec![link.to_owned()],
+        dirs: vec![],
+    };
+    assert_eq!(rt.import_tree(&tree, "0").unwrap().folders_created, 1);
+    assert_eq!(rt.import_tree(&tree, "0").unwrap().folders_reused, 1);
+
+    // an idle connection does not hold the other clients up
+    let idle = std::os::unix::net::UnixStream::connect(&path).unwrap();
+    let start = std::time::Instant::now();
+    assert!(client.profiles().is_ok());
+    assert!(start.elapsed() < std::time::Duration::from_secs(5));
+    drop(idle);
+
+    // an endless request is cut off and answered with an error
+    let mut stream = std::os::unix::net::UnixStream::connect(&path).unwrap();
+    let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+    let writer = std::thread::spawn(move || {
+        let _ = stream.write_all(&vec![b'x'; 1 << 20]);
+    });
+    let mut answer = String::new();
+    reader.read_line(&mut answer).unwrap();
+    assert!(answer.contains("refused"), "{}", answer);
+    writer.join().unwrap();
+    assert!(client.profiles().is_ok());
+
+    let mut rt = Runtime::with_agent(config(), Some("home"), client.clone()).unwrap();
+    assert!(matches!(rt.check_name("github"), Err(Error::NotLoggedIn)));
+
+    client.stop().unwrap();
+    agent.join().unwrap().unwrap();
+    assert!(!path.exists());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(feature = "cli")]
+#[test]
+fn status_through_the_agent_test() {
+    // nothing listens on a port just released
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let config = SessionConfig {
+        endpoints: Endpoints::all(&format!("http://127.0.0.1:{}", port)),
+        retry: rs115::RetryPolicy::none(),
+        ..Default::default()
+    };
+    let mut cache = ProfileCache::default();
+    let profile = rs115::Session::builder(COOKIES).build().unwrap().profile();
+    cache.insert("work", profile).unwrap();
+
+    let dir = std::env::temp_dir().join(format!("rs115-agent-status-{}", std::process::id()));
+    let path = dir.join("agent.sock");
+    let listener = bind(&path).unwrap();
+    let agent = Agent::new(Box::new(MemoryStore::with_cache(cache)), config).unwrap();
+    let agent = std::thread::spawn(move || agent.serve(listener));
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_rs115"))
+        .arg("status")
+        .env(rs115::agent::AGENT_SOCK_ENV, &path)
+        .env("RS115_CONFIG_DIR", &dir)
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(4), "{}", stderr);
+    assert!(stderr.contains("can not reach 115"), "{}", stderr);
+
+    AgentClient::new(&path).stop().unwrap();
+    agent.join().unwrap().unwrap();
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,57 @@
+#![cfg(feature = "async")]
+use std::sync::Arc;
+
+use mock115::MockServer;
+use rs115::{AsyncSession, Endpoints, Error, Sha1Link};
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_rapid_upload_test() {
+    let server = MockServer::builder()
+        .censor(["forbidden"])
+        .all_sha1_known()
+        .start();
+    let mut session = AsyncSession::builder("UID=1")
+        .endpoints(Endpoints::all(server.url()))
+        .build_async()
+        .unwrap();
+    session.get_key_if_none().await.unwrap();
+    let cid = session.create_folder(0, "async".into()).await.unwrap();
+    assert!(matches!(
+        session.create_folder(0, "async".into()).await,
+        Err(Error::DirExist)
+    ));
+    // the answer to a folder made was lost: the retry finds it
+    server.push_response("/files/add", 502, "bad gateway");
+    assert_eq!(session.create_folder(0, "async".into()).await.unwrap(), cid);
+
+    let session = Arc::new(session);
+    let tasks: Vec<_> = (0..8)
+        .map(|i| {
+            let session = Arc::clone(&session);
+            let name = if i == 3 { "forbidden".to_owned() } else { format!("file{}.mkv", i) };
+            tokio::spawn(async move {
+                let link: Sha1Link = format!(
+                    "115://{}|5|702C4E22BE8F3D856C496178C488E86B606D9912|13F48115A678499823003C8331E9C0AD0243F089",
+                    name
+                )
+                .parse()
+                .unwrap();
+                session.rapid_upload(&link.to_request(cid)).await
+            })
+        })
+        .collect();
+
+    let mut forbidden = 0;
+    for task in tasks {
+        match task.await.unwrap() {
+            Ok(()) => {}
+            Err(Error::ForbiddenName) => forbidden += 1,
+            Err(e) => panic!("unexpected {}", e),
+        }
+    }
+    assert_eq!(forbidden, 1);
+    assert_eq!(server.files().len(), 7);
+
+    session.delete_bulk(0, vec![cid]).await.unwrap();
+    assert!(server.folders().is_empty());
+}
@@ -0,0 +1,42 @@
+#![cfg(feature = "blocking")]
+//! Alone in its test binary: it sets the proxy env vars of the process.
+use mock115::MockServer;
+use rs115::{Endpoints, NetworkConfig, Session};
+
+const COOKIES: &str = "UID=1150000_A1_1650000000; CID=abcdef; SEID=0123456789";
+
+#[test]
+fn no_proxy_applies_to_env_proxies_test() {
+    let proxy = MockServer::builder().cookies(COOKIES).start();
+    let server = MockServer::builder().cookies(COOKIES).start();
+    for name in [
+        "http_proxy",
+        "all_proxy",
+        "ALL_PROXY",
+        "no_proxy",
+        "NO_PROXY",
+    ] {
+        std::env::remove_var(name);
+    }
+    std::env::set_var("HTTP_PROXY", proxy.url());
+    let get_key = |no_proxy: Option<&str>| {
+        let network = NetworkConfig {
+            no_proxy: no_proxy.map(str::to_owned),
+            ..Default::default()
+        };
+        let mut session = Session::builder(COOKIES)
+            .endpoints(Endpoints::all(server.url()))
+            .network(network)
+            .build()
+            .unwrap();
+        session.get_key_if_none().unwrap();
+    };
+
+    get_key(None);
+    assert_eq!(proxy.requests().len(), 1);
+    assert!(server.requests().is_empty());
+
+    get_key(Some("localhost,127.0.0.1"));
+    assert_eq!(proxy.requests().len(), 1);
+    assert_eq!(server.requests().len(), 1);
+}
@@ -0,0 +1,152 @@
+#![cfg(feature = "blocking")]
+use mock115::MockServer;
+use rs115::endpoints::Endpoints;
+use rs115::export::{export_tree, export_tree_with_progress, ExportOptions, Symlinks};
+use rs115::functions::Runtime;
+use rs115::hash::HashConfig;
+use rs115::{MemoryStore, RateLimitConfig, SessionConfig, Sha1JsonFolderEntity, Sha1Link};
+use std::path::{Path, PathBuf};
+
+const COOKIES: &str = "UID=1150000_A1_1650000000; CID=abcdef; SEID=0123456789";
+
+/// `show/{a.mkv, notes.txt, .hidden/x.mkv, s1/{b.mkv, extras/c.mkv}, empty/}`
+fn local_tree(test: &str) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("rs115-export-{}-{}", test, std::process::id()));
+    let show = root.join("show");
+    for dir in ["s1/extras", ".hidden", "empty"] {
+        std::fs::create_dir_all(show.join(dir)).unwrap();
+    }
+    for (file, content) in [
+        ("a.mkv", "a"),
+        ("notes.txt", "notes"),
+        (".hidden/x.mkv", "x"),
+        ("s1/b.mkv", "b"),
+        ("s1/extras/c.mkv", "c"),
+    ] {
+        std::fs::write(show.join(file), content).unwrap();
+    }
+    root
+}
+
+fn names(files: &[String]) -> Vec<String> {
+    files
+        .iter()
+        .map(|line| line.parse::<Sha1Link>().unwrap().name)
+        .collect()
+}
+
+#[test]
+fn export_filters_test() {
+    let root = local_tree("filters");
+    let show = root.join("show");
+
+    let all = export_tree(&show, &ExportOptions::default()).unwrap();
+    assert_eq!(all.files, 5);
+    assert_eq!(all.size, 9);
+    assert!(all.failed.is_empty());
+    assert_eq!(all.tree.dir_name, "show");
+    assert_eq!(names(&all.tree.files), ["a.mkv", "notes.txt"]);
+    // sorted, the empty directory left out
+    let dirs: Vec<&str> = all.tree.dirs.iter().map(|d| d.dir_name.as_str()).collect();
+    assert_eq!(dirs, [".hidden", "s1"]);
+
+    // one file after the other gives the same tree
+    let options = ExportOptions {
+        hashing: HashConfig {
+            threads: 1,
+            buffer_size: 3,
+        },
+        ..Default::default()
+    };
+    let mut done = (0, 0);
+    let one = export_tree_with_progress(&show, &options, |p| done = (p.files, p.bytes)).unwrap();
+    assert_eq!(one.tree, all.tree);
+    assert_eq!(done, (5, 9));
+
+    let options = ExportOptions {
+        include: vec!["*.mkv".to_owned()],
+        exclude: vec![".*".to_owned(), "/s1/extras".to_owned()],
+        ..Default::default()
+    };
+    let some = export_tree(&show, &options).unwrap();
+    assert_eq!(some.files, 2);
+    assert_eq!(names(&some.tree.files), ["a.mkv"]);
+    assert_eq!(some.tree.dirs.len(), 1);
+    assert_eq!(names(&some.tree.dirs[0].files), ["b.mkv"]);
+    assert!(some.tree.dirs[0].dirs.is_empty());
+
+    assert!(export_tree(show.join("a.mkv"), &ExportOptions::default()).is_err());
+    let bad_glob = ExportOptions {
+        include: vec!["[".to_owned()],
+        ..Default::default()
+    };
+    assert!(export_tree(&show, &bad_glob).is_err());
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn export_symlinks_test() {
+    let root = local_tree("symlinks");
+    let show = root.join("show");
+    let s1 = show.join("s1");
+    std::os::unix::fs::symlink("../a.mkv", s1.join("a link.mkv")).unwrap();
+    std::os::unix::fs::symlink("..", s1.join("up")).unwrap();
+    std::os::unix::fs::symlink(root.join("gone"), s1.join("broken.mkv")).unwrap();
+    let export = |symlinks| {
+        let options = ExportOptions {
+            include: vec!["*.mkv".to_owned()],
+            exclude: vec![".*".to_owned()],
+            symlinks,
+            ..Default::default()
+        };
+        export_tree(&show, &options).unwrap()
+    };
+    let s1_files = |tree: &Sha1JsonFolderEntity| names(&tree.dirs[0].files);
+
+    let skip = export(Symlinks::Skip);
+    assert_eq!(s1_files(&skip.tree), ["b.mkv"]);
+    assert!(skip.failed.is_empty());
+
+    let files = export(Symlinks::Files);
+    assert_eq!(s1_files(&files.tree), ["a link.mkv", "b.mkv"]);
+    assert_eq!(files.failed.len(), 1);
+    assert_eq!(files.failed[0].0, s1.join("broken.mkv"));
+
+    // the link back up is not walked again
+    let follow = export(Symlinks::Follow);
+    assert_eq!(follow.files, files.files);
+    assert_eq!(follow.tree, files.tree);
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+fn write_json(tree: &Sha1JsonFolderEntity, path: &Path) {
+    std::fs::write(path, serde_json::to_string_pretty(tree).unwrap()).unwrap();
+}
+
+#[test]
+fn exported_tree_imports_test() {
+    let root = local_tree("import");
+    let export = export_tree(root.join("show"), &ExportOptions::default()).unwrap();
+    let json = root.join("show.json");
+    write_json(&export.tree, &json);
+    let tree: Sha1JsonFolderEntity = json.to_str().unwrap().parse().unwrap();
+    assert_eq!(tree, export.tree);
+    std::fs::remove_dir_all(root).unwrap();
+
+    let server = MockServer::builder()
+        .cookies(COOKIES)
+        .all_sha1_known()
+        .start();
+    let config = SessionConfig {
+        endpoints: Endpoints::all(server.url()),
+        rate_limit: RateLimitConfig::unlimited(),
+        ..Default::default()
+    };
+    let mut rt = Runtime::with_store(config, None, Box::new(MemoryStore::new())).unwrap();
+    rt.set_cookies(COOKIES).unwrap();
+    let summary = rt.import_tree(&tree, "0").unwrap();
+    assert!(summary.is_complete());
+    assert_eq!(summary.folders_created, 4);
+    assert_eq!(summary.files.imported, 5);
+}
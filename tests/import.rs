@@ -0,0 +1,253 @@
+#![cfg(feature = "blocking")]
+use mock115::MockServer;
+use rs115::endpoints::Endpoints;
+use rs115::error::Error;
+use rs115::functions::{ImportSummary, Runtime, TreeImportSummary};
+use rs115::{MemoryStore, RateLimitConfig, RetryPolicy, SessionConfig, Sha1JsonFolderEntity};
+use std::time::Duration;
+
+const COOKIES: &str = "UID=1150000_A1_1650000000; CID=abcdef; SEID=0123456789";
+const KNOWN: &str = "702C4E22BE8F3D856C496178C488E86B606D9912";
+const BLOCK: &str = "13F48115A678499823003C8331E9C0AD0243F089";
+
+fn runtime(server: &MockServer) -> Runtime {
+    let config = SessionConfig {
+        endpoints: Endpoints::all(server.url()),
+        rate_limit: RateLimitConfig::unlimited(),
+        retry: RetryPolicy {
+            base_delay: Duration::from_millis(1),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut rt = Runtime::with_store(config, None, Box::new(MemoryStore::new())).unwrap();
+    rt.set_cookies(COOKIES).expect("mock login should succeed");
+    rt
+}
+
+fn link(name: &str, sha1: &str) -> String {
+    format!(
+        "115://{}|19880869888|{}|{}",
+        name,
+        sha1.to_lowercase(),
+        BLOCK
+    )
+}
+
+#[test]
+fn import_outcomes_test() {
+    let server = MockServer::builder()
+        .cookies(COOKIES)
+        .censor(["forbidden"])
+        .known_sha1([KNOWN])
+        .start();
+    let mut rt = runtime(&server);
+    let unknown = "0000000000000000000000000000000000000001";
+    let links = [
+        link("a.iso", KNOWN),
+        String::new(),
+        link("b.iso", unknown),
+        link("forbidden.iso", KNOWN),
+        "not a link".to_owned(),
+    ]
+    .join("\n");
+
+    let mut failed = Vec::new();
+    let summary = rt.import(links.as_bytes(), "0", Some(&mut failed)).unwrap();
+    assert_eq!(
+        summary,
+        ImportSummary {
+            imported: 1,
+            already_exists: 0,
+            not_in_115: 1,
+            forbidden: 1,
+            failed: 1,
+            retries: 0,
+        }
+    );
+    assert!(!summary.is_complete());
+    let failed = String::from_utf8(failed).unwrap();
+    assert_eq!(
+        failed.lines().collect::<Vec<_>>(),
+        [
+            link("b.iso", unknown).as_str(),
+            &link("forbidden.iso", KNOWN),
+            "not a link"
+        ]
+    );
+
+    let files = server.files();
+    assert_eq!(files.len(), 1);
+    assert_eq!((files[0].cid, files[0].name.as_str()), (0, "a.iso"));
+    assert_eq!(files[0].size, "19880869888");
+
+    // importing again finds the file in the folder
+    let summary = rt.import(link("a.iso", KNOWN).as_bytes(), "0", None::<Vec<u8>>);
+    assert_eq!(summary.unwrap().already_exists, 1);
+    assert_eq!(server.files().len(), 1);
+}
+
+#[test]
+fn import_to_path_test() {
+    let server = MockServer::builder()
+        .cookies(COOKIES)
+        .all_sha1_known()
+        .start();
+    let mut rt = runtime(&server);
+    let session = rt.session().unwrap();
+    let movies = session.create_folder(0, "movies".to_owned()).unwrap();
+    let year = session.create_folder(movies, "2024".to_owned()).unwrap();
+
+    let summary = rt
+        .import(
+            link("a.iso", KNOWN).as_bytes(),
+            "/movies/2024/",
+            None::<Vec<u8>>,
+        )
+        .unwrap();
+    assert!(summary.is_complete());
+    assert_eq!(server.files()[0].cid, year);
+
+    let res = rt.import(
+        link("b.iso", KNOWN).as_bytes(),
+        "/movies/1999",
+        None::<Vec<u8>>,
+    );
+    assert!(matches!(res, Err(Error::FolderNotFound(path)) if path == "/movies/1999"));
+    assert_eq!(server.files().len(), 1);
+}
+
+fn tree(
+    dir_name: &str,
+    files: Vec<String>,
+    dirs: Vec<Sha1JsonFolderEntity>,
+) -> Sha1JsonFolderEntity {
+    Sha1JsonFolderEntity {
+        dir_name: dir_name.to_owned(),
+        files,
+        dirs,
+    }
+}
+
+#[test]
+fn import_tree_test() {
+    let server = MockServer::builder()
+        .cookies(COOKIES)
+        .known_sha1([KNOWN])
+        .start();
+    let mut rt = runtime(&server);
+    let unknown = "0000000000000000000000000000000000000001";
+    let show = tree(
+        "show",
+        vec![link("poster.jpg", KNOWN)],
+        vec![
+            tree(
+                "s1",
+                vec![link("e1.mkv", KNOWN), link("e2.mkv", unknown)],
+                vec![],
+            ),
+            tree("s2", vec!["junk".to_owned()], vec![]),
+        ],
+    );
+    // folders already there are reused
+    let session = rt.session().unwrap();
+    let existing = session.create_folder(0, "show".to_owned()).unwrap();
+
+    let summary = rt.import_tree(&show, "0").unwrap();
+    let files = ImportSummary {
+        imported: 2,
+        not_in_115: 1,
+        failed: 1,
+        ..Default::default()
+    };
+    assert_eq!(
+        summary,
+        TreeImportSummary {
+            folders_created: 2,
+            folders_reused: 1,
+            folders_failed: 0,
+            files,
+        }
+    );
+    let folders = server.folders();
+    let s1 = folders.iter().find(|f| f.name == "s1").unwrap();
+    assert_eq!(s1.pid, existing);
+    assert!(folders.iter().any(|f| f.name == "s2" && f.pid == existing));
+    let uploaded = server.files();
+    assert!(uploaded
+        .iter()
+        .any(|f| f.name == "poster.jpg" && f.cid == existing));
+    assert!(uploaded
+        .iter()
+        .any(|f| f.name == "e1.mkv" && f.cid == s1.cid));
+
+    // importing again only finds what is there
+    let again = rt.import_tree(&show, "/").unwrap();
+    assert_eq!((again.folders_created, again.folders_reused), (0, 3));
+    assert_eq!(again.files.already_exists, 2);
+    assert_eq!(server.folders().len(), 3);
+
+    // a tree without a name goes right into the target
+    let loose = tree("", vec![link("loose.iso", KNOWN)], vec![]);
+    let summary = rt.import_tree(&loose, "/show/s2").unwrap();
+    assert!(summary.is_complete());
+    let s2 = server
+        .folders()
+        .into_iter()
+        .find(|f| f.name == "s2")
+        .unwrap();
+    assert!(server
+        .files()
+        .iter()
+        .any(|f| f.name == "loose.iso" && f.cid == s2.cid));
+}
+
+#[test]
+fn hashed_file_imports_test() {
+    let dir = std::env::temp_dir().join(format!("rs115-import-hash-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("local.bin");
+    std::fs::write(&path, vec![7u8; 300_000]).unwrap();
+    let link = rs115::hash::hash_file(&path).unwrap();
+    std::fs::remove_dir_all(dir).unwrap();
+
+    let server = MockServer::builder()
+        .cookies(COOKIES)
+        .known_sha1([link.sha1.as_str()])
+        .start();
+    let mut rt = runtime(&server);
+    let summary = rt
+        .import(link.to_string().as_bytes(), "0", None::<Vec<u8>>)
+        .unwrap();
+    assert_eq!(summary.imported, 1);
+    let uploaded = &server.files()[0];
+    assert_eq!(uploaded.name, "local.bin");
+    assert_eq!(uploaded.size, "300000");
+    assert_eq!(uploaded.sha1, link.sha1);
+}
+
+#[test]
+fn import_retries_test() {
+    let server = MockServer::builder()
+        .cookies(COOKIES)
+        .known_sha1([KNOWN])
+        .start();
+    let mut rt = runtime(&server);
+    server.push_response("/3.0/initupload.php", 502, "bad gateway");
+    server.push_response(
+        "/3.0/initupload.php",
+        200,
+        r#"{"statuscode":429,"statusmsg":"too fast"}"#,
+    );
+    let links = [link("a.iso", KNOWN), link("b.iso", KNOWN)].join("\n");
+    let summary = rt.import(links.as_bytes(), "0", None::<Vec<u8>>).unwrap();
+    assert_eq!((summary.imported, summary.retries), (2, 2));
+    assert!(summary.is_complete());
+    assert!(summary.to_string().ends_with("\n2 requests retried"));
+
+    // counted again for each run
+    server.push_response("/3.0/initupload.php", 503, "unavailable");
+    let tree = tree("", vec![link("c.iso", KNOWN)], vec![]);
+    let summary = rt.import_tree(&tree, "0").unwrap();
+    assert_eq!((summary.files.imported, summary.files.retries), (1, 1));
+}
@@ -0,0 +1,358 @@
+#![cfg(feature = "blocking")]
+use mock115::MockServer;
+use rs115::endpoints::Endpoints;
+use rs115::error::Error;
+use rs115::functions::Runtime;
+use rs115::{EnvStore, MemoryStore, RetryPolicy, SessionConfig};
+use std::time::Duration;
+
+const COOKIES: &str = "UID=1150000_A1_1650000000; CID=abcdef; SEID=0123456789";
+
+fn runtime(server: &MockServer) -> Runtime {
+    let mut rt = in_memory(SessionConfig {
+        endpoints: Endpoints::all(server.url()),
+        ..Default::default()
+    });
+    rt.set_cookies(COOKIES).expect("mock login should succeed");
+    rt
+}
+
+fn in_memory(config: SessionConfig) -> Runtime {
+    Runtime::with_store(config, None, Box::new(MemoryStore::new())).unwrap()
+}
+
+#[test]
+fn check_name_test() {
+    let server = MockServer::builder()
+        .cookies(COOKIES)
+        .censor(["forbidden"])
+        .all_sha1_known()
+        .start();
+    let mut rt = runtime(&server);
+
+    assert!(rt.check_name("github").unwrap());
+    assert!(!rt.check_name("a forbidden name").unwrap());
+    // the temporary folder is always cleaned up
+    assert!(server.folders().is_empty());
+}
+
+#[test]
+fn check_name_unknown_hash_fails_test() {
+    let server = MockServer::builder().cookies(COOKIES).start();
+    let mut rt = runtime(&server);
+
+    assert!(rt.check_name("github").is_err());
+    assert!(server.folders().is_empty());
+}
+
+#[test]
+fn cookies_from_env_test() {
+    let server = MockServer::builder()
+        .cookies(COOKIES)
+        .all_sha1_known()
+        .start();
+    let var = "RS115_TEST_MOCK_CHECK_COOKIES";
+    std::env::set_var(var, COOKIES);
+    let config = SessionConfig {
+        endpoints: Endpoints::all(server.url()),
+        ..Default::default()
+    };
+    let mut rt = Runtime::with_store(config, None, Box::new(EnvStore::with_var(var))).unwrap();
+    std::env::remove_var(var);
+
+    // the user key is fetched on first use, nothing can be saved
+    assert!(rt.session().unwrap().user_key().is_none());
+    assert!(rt.check_name("github").unwrap());
+    assert!(rt.set_cookies(COOKIES).is_err());
+    assert!(rt.clean().is_err());
+}
+
+#[test]
+fn stale_user_key_is_refreshed_test() {
+    let server = MockServer::builder()
+        .cookies(COOKIES)
+        .all_sha1_known()
+        .start();
+    let store = MemoryStore::new();
+    let config = SessionConfig {
+        endpoints: Endpoints::all(server.url()),
+        ..Default::default()
+    };
+    let mut rt = Runtime::with_store(config, None, Box::new(store.clone())).unwrap();
+    rt.set_cookies(COOKIES).unwrap();
+
+    server.set_user_key("fresh");
+    assert!(rt.check_name("github").unwrap());
+    // rejected once, fetched again and retried
+    assert_eq!(server.requests_to("/3.0/initupload.php").len(), 2);
+    assert_eq!(rt.session().unwrap().key_refreshes(), 1);
+    let saved = store.snapshot().profiles["default"].clone();
+    assert_eq!(saved.user_key.as_deref(), Some("fresh"));
+    assert!(server.folders().is_empty());
+}
+
+#[test]
+fn debug_output_is_redacted_test() {
+    let server = MockServer::builder()
+        .cookies(COOKIES)
+        .user(7654321, "secretkey")
+        .all_sha1_known()
+        .start();
+    let mut rt = runtime(&server);
+    assert!(rt.check_name("github").unwrap());
+
+    let debug = format!("{:?}", rt);
+    assert!(debug.contains("UID=***; CID=***; SEID=***"));
+    for secret in ["1150000", "abcdef", "0123456789", "7654321", "secretkey"] {
+        assert!(!debug.contains(secret), "{} in {}", secret, debug);
+    }
+}
+
+#[test]
+fn check_name_bulk_to_file_test() {
+    let server = MockServer::builder()
+        .cookies(COOKIES)
+        .censor(["bad"])
+        .all_sha1_known()
+        .start();
+    let mut rt = runtime(&server);
+    server.push_response(
+        "/3.0/initupload.php",
+        200,
+        r#"{"statuscode":1,"statusmsg":"busy"}"#,
+    );
+
+    let names = "first\nbad word\nthird\n".as_bytes();
+    let mut forbidden = Vec::new();
+    let mut failed = Vec::new();
+    rt.check_name_bulk_to_file(names, Some(&mut forbidden), Some(&mut failed), Some(0))
+        .unwrap();
+
+    assert_eq!(String::from_utf8(forbidden).unwrap(), "bad word\n");
+    assert_eq!(String::from_utf8(failed).unwrap(), "first\n");
+    assert_eq!(server.requests_to("/3.0/initupload.php").len(), 3);
+    assert!(server.folders().is_empty());
+}
+
+#[test]
+fn malformed_response_is_an_error_test() {
+    let server = MockServer::builder()
+        .cookies(COOKIES)
+        .all_sha1_known()
+        .start();
+    let mut rt = runtime(&server);
+
+    server.push_response("/3.0/initupload.php", 200, "<html>not json</html>");
+    match rt.check_name("github") {
+        Err(Error::Decode { snippet, .. }) => assert_eq!(snippet, "<html>not json</html>"),
+        res => panic!("unexpected {:?}", res),
+    }
+
+    server.push_response("/3.0/initupload.php", 404, "not found");
+    assert!(matches!(
+        rt.check_name("github"),
+        Err(Error::HttpStatus { status: 404, .. })
+    ));
+    assert!(server.folders().is_empty());
+}
+
+#[test]
+fn expired_cookies_test() {
+    let server = MockServer::builder().cookies("UID=someone_else").start();
+    let mut rt = in_memory(SessionConfig {
+        endpoints: Endpoints::all(server.url()),
+        ..Default::default()
+    });
+
+    assert!(matches!(rt.set_cookies(COOKIES), Err(Error::AuthExpired)));
+}
+
+#[test]
+fn pasted_cookies_are_normalised_test() {
+    let server = MockServer::builder().cookies(COOKIES).start();
+    let mut rt = in_memory(SessionConfig {
+        endpoints: Endpoints::all(server.url()),
+        ..Default::default()
+    });
+
+    assert!(matches!(
+        rt.set_cookies("UID=1150000_A1_1650000000; SEID=0123456789"),
+        Err(Error::Cookies(_))
+    ));
+    assert!(server.requests().is_empty());
+
+    let warnings = rt
+        .set_cookies(
+            "Cookie: UID=1150000_A1_1650000000;\n CID=abcdef;SEID=0123456789; CID=abcdef\n",
+        )
+        .unwrap();
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(rt.print_cookies(), COOKIES);
+}
+
+#[test]
+fn transient_failures_are_retried_test() {
+    let server = MockServer::builder()
+        .cookies(COOKIES)
+        .all_sha1_known()
+        .start();
+    let config = SessionConfig {
+        endpoints: Endpoints::all(server.url()),
+        retry: RetryPolicy {
+            base_delay: Duration::from_millis(1),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut rt = in_memory(config);
+    rt.set_cookies(COOKIES).unwrap();
+
+    server.push_response("/3.0/initupload.php", 502, "bad gateway");
+    server.push_response(
+        "/3.0/initupload.php",
+        200,
+        r#"{"statuscode":429,"statusmsg":"too fast"}"#,
+    );
+    let mut failed = Vec::new();
+    rt.check_name_bulk_to_file("flaky\n".as_bytes(), None, Some(&mut failed), Some(0))
+        .unwrap();
+
+    assert!(failed.is_empty());
+    assert_eq!(rt.session().unwrap().retry_count(), 2);
+    assert_eq!(server.requests_to("/3.0/initupload.php").len(), 3);
+
+    // out of attempts: the name lands in the failed list
+    for _ in 0..3 {
+        server.push_response("/3.0/initupload.php", 503, "unavailable");
+    }
+    rt.check_name_bulk_to_file("flaky\n".as_bytes(), None, Some(&mut failed), Some(0))
+        .unwrap();
+    assert_eq!(String::from_utf8(failed).unwrap(), "flaky\n");
+}
+
+#[test]
+fn rate_limit_answers_slow_the_session_down_test() {
+    let server = MockServer::builder()
+        .cookies(COOKIES)
+        .all_sha1_known()
+        .start();
+    let config = SessionConfig {
+        endpoints: Endpoints::all(server.url()),
+        retry: RetryPolicy {
+            base_delay: Duration::from_millis(50),
+            jitter: false,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut rt = in_memory(config);
+    rt.set_cookies(COOKIES).unwrap();
+
+    let limiter = rt.session().unwrap().rate_limiter();
+    assert_eq!(limiter.throttle_factor(), 1.0);
+    server.push_response(
+        "/3.0/initupload.php",
+        200,
+        r#"{"statuscode":429,"statusmsg":"too fast"}"#,
+    );
+    server.push_response("/3.0/initupload.php", 429, "too many requests");
+    let mut failed = Vec::new();
+    rt.check_name_bulk_to_file("a\n".as_bytes(), None, Some(&mut failed), None)
+        .unwrap();
+
+    // both answers halved the rate, the successes since gave a bit back
+    assert!(failed.is_empty());
+    let factor = limiter.throttle_factor();
+    assert!(factor > 0.25 && factor < 0.5, "factor {}", factor);
+    assert_eq!(server.requests_to("/3.0/initupload.php").len(), 3);
+}
+
+#[test]
+fn verification_stops_bulk_check_test() {
+    let server = MockServer::builder()
+        .cookies(COOKIES)
+        .all_sha1_known()
+        .start();
+    let mut rt = runtime(&server);
+    rt.set_pause_on_verification(false);
+
+    server.push_response("/3.0/initupload.php", 200, r#"{"statuscode":0,"status":2}"#);
+    server.push_response(
+        "/3.0/initupload.php",
+        200,
+        r#"{"statuscode":911,"statusmsg":"please verify"}"#,
+    );
+    let mut failed = Vec::new();
+    let res = rt.check_name_bulk_to_file("a\nb\nc\n".as_bytes(), None, Some(&mut failed), Some(0));
+
+    assert!(matches!(res, Err(Error::VerificationRequired { .. })));
+    // not retried, and everything left to check is kept
+    assert_eq!(server.requests_to("/3.0/initupload.php").len(), 2);
+    assert_eq!(String::from_utf8(failed).unwrap(), "b\nc\n");
+    // the temporary folder is deleted on the way out
+    assert!(server.folders().is_empty());
+
+    server.set_verification_required(true);
+    assert!(matches!(
+        rt.check_name("a"),
+        Err(Error::VerificationRequired { .. })
+    ));
+}
+
+#[test]
+fn account_info_test() {
+    let server = MockServer::builder()
+        .cookies(COOKIES)
+        .user_name("someone")
+        .vip(1893456000)
+        .all_sha1_known()
+        .start();
+    let rt = runtime(&server);
+    rt.session()
+        .unwrap()
+        .rapid_upload(&rs115::RapidUploadRequest {
+            filename: "a.mkv".into(),
+            file_size: 1024,
+            sha1: "702C4E22BE8F3D856C496178C488E86B606D9912".into(),
+            block_sha1: "13F48115A678499823003C8331E9C0AD0243F089".into(),
+            cid: 0,
+        })
+        .unwrap();
+
+    let info = rt.account_info().unwrap();
+    assert_eq!(info.user_id, mock115::DEFAULT_USER_ID.to_string());
+    assert_eq!(info.user_name, "someone");
+    assert!(info.vip);
+    assert_eq!(info.vip_expire, Some(1893456000));
+    assert_eq!(
+        (info.space_total, info.space_used),
+        (mock115::SPACE_TOTAL, 1024)
+    );
+    assert!(info.user_key_valid);
+
+    server.set_user_key("rotated");
+    assert!(!rt.account_info().unwrap().user_key_valid);
+}
+
+#[test]
+fn account_info_expired_cookies_test() {
+    let server = MockServer::builder().cookies(COOKIES).start();
+    let session = rs115::Session::builder("UID=1; CID=old; SEID=old")
+        .endpoints(Endpoints::all(server.url()))
+        .user_key("1150000", "old")
+        .build()
+        .unwrap();
+    assert!(matches!(session.account_info(), Err(Error::AuthExpired)));
+}
+
+#[test]
+fn account_info_of_another_user_test() {
+    let server = MockServer::builder().cookies(COOKIES).start();
+    // cookies of the mock user, user key cached for someone else
+    let session = rs115::Session::builder(COOKIES)
+        .endpoints(Endpoints::all(server.url()))
+        .user_key("42", "old")
+        .build()
+        .unwrap();
+    assert!(matches!(session.account_info(), Err(Error::AuthExpired)));
+}
@@ -0,0 +1,133 @@
+#![cfg(feature = "blocking")]
+use mock115::MockServer;
+use rs115::store::SessionStore;
+use rs115::{ClientIdentity, Endpoints, Error, FileStore, MemoryStore, Runtime, SessionConfig};
+
+const WORK: &str = "UID=1_A1_1; CID=work; SEID=work";
+const HOME: &str = "UID=2_A1_2; CID=home; SEID=home";
+
+#[test]
+fn profiles_test() {
+    let server = MockServer::builder().start();
+    let store = MemoryStore::new();
+    let profile = |name: Option<&str>| {
+        let config = SessionConfig {
+            endpoints: Endpoints::all(server.url()),
+            ..Default::default()
+        };
+        Runtime::with_store(config, name, Box::new(store.clone())).unwrap()
+    };
+
+    profile(Some("work")).set_cookies(WORK).unwrap();
+    profile(Some("home")).set_cookies(HOME).unwrap();
+    // the first profile is the default
+    let rt = profile(None);
+    assert_eq!(rt.profile_name(), "work");
+    assert_eq!(rt.print_cookies(), WORK);
+    assert_eq!(profile(Some("home")).print_cookies(), HOME);
+    assert!(!profile(Some("nobody")).has_cookies());
+
+    let mut rt = profile(None);
+    rt.use_profile("home").unwrap();
+    assert!(matches!(
+        rt.use_profile("nobody"),
+        Err(Error::UnknownProfile(_))
+    ));
+    assert_eq!(profile(None).print_cookies(), HOME);
+
+    rt.remove_profile("home").unwrap();
+    let mut rt = profile(None);
+    assert_eq!(rt.profile_name(), "work");
+    assert_eq!(rt.profiles().profiles.keys().collect::<Vec<_>>(), ["work"]);
+    assert!(matches!(
+        profile(Some("a b")).set_cookies(WORK),
+        Err(Error::InvalidProfileName(_))
+    ));
+    rt.clean().unwrap();
+    assert!(!rt.has_cookies());
+    assert!(store.snapshot().profiles.is_empty());
+}
+
+#[test]
+fn client_per_profile_test() {
+    let server = MockServer::builder()
+        .all_sha1_known()
+        .min_app_version("30")
+        .start();
+    let store = MemoryStore::new();
+    let runtime = |name: &str, client: Option<ClientIdentity>| {
+        let config = SessionConfig {
+            endpoints: Endpoints::all(server.url()),
+            client,
+            ..Default::default()
+        };
+        Runtime::with_store(config, Some(name), Box::new(store.clone())).unwrap()
+    };
+
+    let mut rt = runtime("work", None);
+    rt.set_cookies(WORK).unwrap();
+    runtime("home", None).set_cookies(HOME).unwrap();
+    match rt.check_name("github") {
+        Err(e @ Error::ClientRetired { .. }) => {
+            assert!(e.to_string().contains("29.0.0"));
+            assert!(e.to_string().contains(ClientIdentity::newest_preset()));
+        }
+        res => panic!("unexpected {:?}", res),
+    }
+
+    let newer = ClientIdentity::preset("desktop-30").unwrap();
+    rt.set_client(Some(newer.clone())).unwrap();
+    assert!(rt.check_name("github").unwrap());
+    let upload = server.requests_to("/3.0/initupload.php").pop().unwrap();
+    assert_eq!(upload.query["appversion"], newer.app_version);
+    assert_eq!(upload.form["app_ver"], newer.app_version);
+    assert_eq!(upload.user_agent, Some(newer.user_agent.clone()));
+
+    // kept in the store and over a new login, other profiles keep the default
+    let mut rt = runtime("work", None);
+    assert_eq!(rt.client(), newer);
+    rt.set_cookies(WORK).unwrap();
+    assert_eq!(runtime("work", None).client(), newer);
+    assert_eq!(runtime("home", None).client(), ClientIdentity::default());
+    // the config wins for one run
+    let old = runtime("work", Some(ClientIdentity::default()));
+    assert_eq!(old.client(), ClientIdentity::default());
+
+    rt.set_client(None).unwrap();
+    assert_eq!(store.snapshot().profiles["work"].client, None);
+}
+
+#[test]
+fn file_store_migrates_legacy_cache_test() {
+    let server = MockServer::builder().start();
+    let dir = std::env::temp_dir().join(format!("rs115-profiles-{}", std::process::id()));
+    let legacy = dir.join(".COOKIES_115.cache");
+    std::fs::create_dir_all(&dir).unwrap();
+    // a single session cache from before profiles
+    std::fs::write(
+        &legacy,
+        format!(r#"{{"cookies":"{}","user_id":"1","user_key":"k"}}"#, WORK),
+    )
+    .unwrap();
+
+    let store = FileStore::new(dir.join("config").join("cookies.json")).legacy(&legacy);
+    let config = SessionConfig {
+        endpoints: Endpoints::all(server.url()),
+        ..Default::default()
+    };
+    let mut rt = Runtime::with_store(config, Some("home"), Box::new(store.clone())).unwrap();
+    assert_eq!(rt.profiles().resolve(None), "default");
+    assert_eq!(
+        rt.profiles().get("default").unwrap().user_key.as_deref(),
+        Some("k")
+    );
+
+    // the first save writes the new file and deletes the old one
+    rt.set_cookies(HOME).unwrap();
+    assert!(store.path().exists() && !legacy.exists());
+    assert_eq!(store.load().unwrap().profiles.len(), 2);
+
+    rt.clean().unwrap();
+    assert!(!store.path().exists() && !legacy.exists());
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,68 @@
+#![cfg(feature = "blocking")]
+use std::time::Duration;
+
+use mock115::{MockServer, QR_CONFIRMED, QR_EXPIRED, QR_SCANNED, QR_WAITING};
+use rs115::{Endpoints, Error, MemoryStore, QrStatus, Runtime, SessionConfig};
+
+#[test]
+fn qr_login_test() {
+    let server = MockServer::builder()
+        .qr_statuses([QR_WAITING, QR_WAITING, QR_SCANNED, QR_CONFIRMED])
+        .start();
+    let mut rt = Runtime::with_store(
+        SessionConfig {
+            endpoints: Endpoints::all(server.url()),
+            ..Default::default()
+        },
+        None,
+        Box::new(MemoryStore::new()),
+    )
+    .unwrap();
+
+    let qr = rt.qr_login().unwrap();
+    assert!(qr.qr_text().starts_with("https://115.com/scan/"));
+    let mut seen = Vec::new();
+    let cookies = qr
+        .wait(Duration::from_millis(1), |status| seen.push(status))
+        .unwrap();
+    // repeated statuses are reported once
+    assert_eq!(
+        seen,
+        [QrStatus::Waiting, QrStatus::Scanned, QrStatus::Confirmed]
+    );
+    assert_eq!(server.requests_to("/get/status/").len(), 4);
+    assert_eq!(
+        cookies,
+        "UID=1150000_A1_1650000000; CID=mockcid; SEID=mockseid"
+    );
+
+    // stored like pasted cookies, and good for the rest of the api
+    rt.set_cookies(&cookies).unwrap();
+    assert_eq!(rt.session().unwrap().user_id().as_deref(), Some("1150000"));
+    assert_eq!(rt.print_cookies(), cookies);
+}
+
+#[test]
+fn qr_login_expired_test() {
+    let server = MockServer::builder()
+        .qr_statuses([QR_WAITING, QR_EXPIRED])
+        .start();
+    let rt = Runtime::with_store(
+        SessionConfig {
+            endpoints: Endpoints::all(server.url()),
+            ..Default::default()
+        },
+        None,
+        Box::new(MemoryStore::new()),
+    )
+    .unwrap();
+
+    let qr = rt.qr_login().unwrap();
+    assert_eq!(qr.poll().unwrap(), QrStatus::Waiting);
+    assert!(matches!(
+        qr.wait(Duration::from_millis(1), |_| {}),
+        Err(Error::QrLogin(QrStatus::Expired))
+    ));
+    // no cookies for a code that was never confirmed
+    assert!(matches!(qr.cookies(), Err(Error::Api { .. })));
+}
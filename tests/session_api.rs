@@ -0,0 +1,102 @@
+#![cfg(feature = "blocking")]
+use mock115::MockServer;
+use rs115::{Endpoints, Error, Session, Sha1Link};
+
+const LINK: &str = "115://movie.mkv|19880869888|702C4E22BE8F3D856C496178C488E86B606D9912|13F48115A678499823003C8331E9C0AD0243F089";
+
+#[test]
+fn session_builder_round_trip_test() {
+    let server = MockServer::builder()
+        .known_sha1(["702C4E22BE8F3D856C496178C488E86B606D9912"])
+        .start();
+    let mut session = Session::builder("UID=1")
+        .endpoints(Endpoints::all(server.url()))
+        .user_agent("rs115-test")
+        .build()
+        .unwrap();
+    session.get_key_if_none().unwrap();
+    assert_eq!(session.user_id().as_deref(), Some("1150000"));
+
+    let cid = session.create_folder(0, "movies".into()).unwrap();
+    assert!(matches!(
+        session.create_folder(0, "movies".into()),
+        Err(Error::DirExist)
+    ));
+
+    let link: Sha1Link = LINK.parse().unwrap();
+    session.rapid_upload(&link.to_request(cid)).unwrap();
+    let files = server.files();
+    assert_eq!(files.len(), 1);
+    assert_eq!((files[0].cid, files[0].name.as_str()), (cid, "movie.mkv"));
+
+    // the answer to a folder made was lost: the retry finds it
+    server.push_response("/files/add", 502, "bad gateway");
+    assert_eq!(session.create_folder(0, "movies".into()).unwrap(), cid);
+    assert_eq!(server.folders().len(), 1);
+
+    session.delete_one(0, cid).unwrap();
+    assert!(server.folders().is_empty());
+    let requests = server.requests();
+    assert!(requests
+        .iter()
+        .all(|r| r.user_agent.as_deref() == Some("rs115-test")));
+}
+
+#[test]
+fn restored_session_reuses_user_key_test() {
+    let server = MockServer::builder().start();
+    let session = Session::builder("UID=1")
+        .endpoints(Endpoints::all(server.url()))
+        .user_key("42", "key")
+        .build()
+        .unwrap();
+    let saved = serde_json::to_string(&session).unwrap();
+    assert!(saved.contains(r#""user_id":"42","user_key":"key""#));
+    let restored: Session = serde_json::from_str(&saved).unwrap();
+    let mut restored = restored
+        .restore(rs115::SessionConfig {
+            endpoints: Endpoints::all(server.url()),
+            ..Default::default()
+        })
+        .unwrap();
+
+    restored.get_key_if_none().unwrap();
+    assert_eq!(restored.user_key().as_deref(), Some("key"));
+    assert!(server.requests().is_empty());
+}
+
+#[test]
+fn sessions_share_one_rate_limit_test() {
+    use rs115::{RateLimit, RateLimitConfig, RateLimiter};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    let server = MockServer::builder().start();
+    let limiter = Arc::new(RateLimiter::new(&RateLimitConfig {
+        global: Some(RateLimit::new(10.0, 2)),
+        ..RateLimitConfig::unlimited()
+    }));
+    let start = Instant::now();
+    let workers: Vec<_> = (0..2)
+        .map(|i| {
+            let mut session = Session::builder("UID=1")
+                .endpoints(Endpoints::all(server.url()))
+                .user_key("42", "key")
+                .rate_limiter(Arc::clone(&limiter))
+                .build()
+                .unwrap();
+            std::thread::spawn(move || {
+                for j in 0..3 {
+                    session.create_folder(0, format!("{}-{}", i, j)).unwrap();
+                }
+                session.get_key_if_none().unwrap();
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    // 6 requests, 2 of them in the burst, the other 4 at 10 per second
+    assert!(start.elapsed() >= Duration::from_millis(390));
+    assert_eq!(server.folders().len(), 6);
+}